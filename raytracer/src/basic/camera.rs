@@ -1,5 +1,6 @@
 use super::ray::Ray;
-use super::vec3::{cross, random_double_lr, random_in_unit_disk, unit_vector, Point3, Vec3};
+use super::vec3::{cross, random_in_unit_disk, unit_vector, Point3, Vec3};
+use crate::sampler::Sampler;
 use std::f64::consts::PI;
 
 fn degrees_to_radians(degrees: f64) -> f64 {
@@ -21,17 +22,22 @@ pub struct Camera {
 }
 
 impl Camera {
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = random_in_unit_disk() * (*self).lens_radius;
+    pub fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Ray {
+        let rd = random_in_unit_disk(sampler) * (*self).lens_radius;
         let offset = (*self).u * rd.x() + (*self).v * rd.y();
         Ray {
             st: (*self).origin + offset,
             dir: (*self).lower_left_corner + (*self).horizontal * s + (*self).vertical * t
                 - (*self).origin
                 - offset,
-            tm: random_double_lr((*self).time0, (*self).time1),
+            tm: (*self).time0 + sampler.get_1d() * ((*self).time1 - (*self).time0),
         }
     }
+    pub fn set_aspect_ratio(&mut self, old: f64, new: f64) {
+        let horizontal = (*self).horizontal * (new / old);
+        (*self).lower_left_corner += ((*self).horizontal - horizontal) / 2.0;
+        (*self).horizontal = horizontal;
+    }
     #[allow(clippy::too_many_arguments)]
     pub fn creat(
         lookfrom: Point3,
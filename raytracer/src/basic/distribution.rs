@@ -0,0 +1,165 @@
+// Piecewise constant distribution over [0, 1) proportional to `func`.
+#[derive(Clone)]
+pub struct Distribution1d {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1d {
+    pub fn creat(func: &[f64]) -> Distribution1d {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i].max(0.0) / n as f64;
+        }
+        let integral = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate() {
+            // all zero, fall back to uniform
+            *c = if integral > 0.0 {
+                *c / integral
+            } else {
+                i as f64 / n as f64
+            };
+        }
+        Distribution1d {
+            func: func.iter().map(|f| f.max(0.0)).collect(),
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn integral(&self) -> f64 {
+        (*self).integral
+    }
+
+    // Density at `x` in [0, 1).
+    pub fn pdf(&self, x: f64) -> f64 {
+        let n = (*self).func.len();
+        (*self).piece_pdf(((x * n as f64) as usize).min(n - 1))
+    }
+
+    // Density over the `i`th piece.
+    pub fn piece_pdf(&self, i: usize) -> f64 {
+        if (*self).integral > 0.0 {
+            (*self).func[i] / (*self).integral
+        } else {
+            1.0
+        }
+    }
+
+    // Maps `u` in [0, 1) to a point distributed like `func`, returns the
+    // point and its density.
+    pub fn sample(&self, u: f64) -> (f64, f64) {
+        let n = (*self).func.len();
+        // last i with cdf[i] <= u, which skips zero width pieces
+        let i = (*self)
+            .cdf
+            .partition_point(|c| *c <= u)
+            .saturating_sub(1)
+            .min(n - 1);
+        let width = (*self).cdf[i + 1] - (*self).cdf[i];
+        let du = if width > 0.0 {
+            (u - (*self).cdf[i]) / width
+        } else {
+            0.0
+        };
+        let x = ((i as f64 + du) / n as f64).min(1.0 - f64::EPSILON);
+        (x, (*self).piece_pdf(i))
+    }
+}
+
+// Piecewise constant distribution over [0, 1)^2 proportional to a row major
+// `width` x `height` grid of values, sampled row first.
+#[derive(Clone)]
+pub struct Distribution2d {
+    rows: Vec<Distribution1d>,
+    marginal: Distribution1d,
+}
+
+impl Distribution2d {
+    pub fn creat(func: &[f64], width: usize, height: usize) -> Distribution2d {
+        let rows: Vec<Distribution1d> = (0..height)
+            .map(|y| Distribution1d::creat(&func[y * width..(y + 1) * width]))
+            .collect();
+        let marginal =
+            Distribution1d::creat(&rows.iter().map(|r| r.integral()).collect::<Vec<_>>());
+        Distribution2d { rows, marginal }
+    }
+
+    pub fn pdf(&self, x: f64, y: f64) -> f64 {
+        let n = (*self).rows.len();
+        let row = ((y * n as f64) as usize).min(n - 1);
+        (*self).marginal.pdf(y) * (*self).rows[row].pdf(x)
+    }
+
+    pub fn sample(&self, u: f64, v: f64) -> ((f64, f64), f64) {
+        let (y, pdf_y) = (*self).marginal.sample(v);
+        let rows = (*self).rows.len();
+        let row = ((y * rows as f64) as usize).min(rows - 1);
+        let (x, pdf_x) = (*self).rows[row].sample(u);
+        ((x, y), pdf_x * pdf_y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FUNC: [f64; 6] = [1.0, 0.0, 3.0, 0.5, 0.0, 2.5];
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        let dist = Distribution1d::creat(&FUNC);
+        assert!((dist.integral() - 7.0 / 6.0).abs() < 1e-12);
+        let n = 6000;
+        let total: f64 = (0..n)
+            .map(|i| dist.pdf((i as f64 + 0.5) / n as f64) / n as f64)
+            .sum();
+        assert!((total - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn samples_land_in_proportion_to_the_function() {
+        let dist = Distribution1d::creat(&FUNC);
+        let n = 70000;
+        let mut hits = [0usize; 6];
+        for i in 0..n {
+            let (x, pdf) = dist.sample((i as f64 + 0.5) / n as f64);
+            assert!((0.0..1.0).contains(&x));
+            assert!((pdf - dist.pdf(x)).abs() < 1e-12);
+            hits[(x * 6.0) as usize] += 1;
+        }
+        for (h, f) in hits.iter().zip(FUNC.iter()) {
+            assert!((*h as f64 / n as f64 - f / 7.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn all_zero_is_uniform() {
+        let dist = Distribution1d::creat(&[0.0; 4]);
+        assert!((dist.pdf(0.3) - 1.0).abs() < 1e-12);
+        let (x, pdf) = dist.sample(0.6);
+        assert!((x - 0.6).abs() < 1e-12);
+        assert!((pdf - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn distribution_2d_pdf_matches_its_samples() {
+        let func: Vec<f64> = (0..12).map(|i| ((i * 7) % 5) as f64).collect();
+        let dist = Distribution2d::creat(&func, 4, 3);
+        let steps = 240;
+        let cell = 1.0 / steps as f64;
+        let mut total = 0.0;
+        for row in 0..steps {
+            for column in 0..steps {
+                let u = (column as f64 + 0.5) * cell;
+                let v = (row as f64 + 0.5) * cell;
+                total += dist.pdf(u, v) * cell * cell;
+                let (point, pdf) = dist.sample(u, v);
+                assert!((pdf - dist.pdf(point.0, point.1)).abs() < 1e-9);
+            }
+        }
+        assert!((total - 1.0).abs() < 1e-9);
+    }
+}
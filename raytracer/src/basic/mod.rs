@@ -1,4 +1,5 @@
 pub mod camera;
+pub mod distribution;
 pub mod onb;
 pub mod ray;
 pub mod vec3;
@@ -1,4 +1,4 @@
-use super::vec3::{cross, unit_vector, Vec3};
+use super::vec3::{cross, dot, unit_vector, Vec3};
 
 #[derive(Default, Copy, Clone, Debug)]
 pub struct Onb {
@@ -21,6 +21,12 @@ impl Onb {
     pub fn local_vec(&self, v: Vec3) -> Vec3 {
         (*self).axis[0] * v.e[0] + (*self).axis[1] * v.e[1] + (*self).axis[2] * v.e[2]
     }
+    // inverse of `local_vec`
+    pub fn to_local(self, v: Vec3) -> Vec3 {
+        Vec3 {
+            e: [dot(v, self.u()), dot(v, self.v()), dot(v, self.w())],
+        }
+    }
     pub fn build_from_w(&mut self, n: Vec3) {
         (*self).axis[2] = unit_vector(n);
         let a = if self.w().x().abs() > 0.9 {
@@ -31,4 +37,17 @@ impl Onb {
         (*self).axis[1] = unit_vector(cross(self.w(), a));
         (*self).axis[0] = cross(self.w(), self.v());
     }
+    // Like `build_from_w`, with u along `t` projected onto the plane of `n`.
+    // Falls back to `build_from_w` when that projection vanishes.
+    pub fn build_from_w_u(&mut self, n: Vec3, t: Vec3) {
+        let w = unit_vector(n);
+        let u = t - w * dot(t, w);
+        if u.length_squared() < 1e-12 {
+            (*self).build_from_w(n);
+            return;
+        }
+        (*self).axis[2] = w;
+        (*self).axis[0] = unit_vector(u);
+        (*self).axis[1] = cross(self.w(), self.u());
+    }
 }
@@ -1,9 +1,20 @@
 use super::fmin;
+use crate::sampler::Sampler;
 use rand::prelude::*;
+use std::cell::RefCell;
+use std::f64::consts::PI;
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign};
 
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+pub fn seed_random(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
 pub fn random_double() -> f64 {
-    rand::thread_rng().gen::<f64>()
+    RNG.with(|rng| rng.borrow_mut().gen::<f64>())
 }
 
 pub fn random_double_lr(min: f64, max: f64) -> f64 {
@@ -186,17 +197,14 @@ pub fn unit_vector(v: Vec3) -> Vec3 {
     v / v.length()
 }
 
-pub fn random_in_unit_sphere() -> Vec3 {
-    loop {
-        let p = Vec3::random_lr(-1.0, 1.0);
-        if p.length_squared() < 1.0 {
-            break p;
-        }
-    }
+// The samplers below map their sample dimensions directly instead of rejection
+// sampling, so that every call consumes a fixed number of dimensions.
+pub fn random_in_unit_sphere(sampler: &mut dyn Sampler) -> Vec3 {
+    random_unit_vector(sampler) * sampler.get_1d().cbrt()
 }
 
-pub fn random_in_hemisphere(normal: Vec3) -> Vec3 {
-    let in_unit_sphere = random_in_unit_sphere();
+pub fn random_in_hemisphere(normal: Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+    let in_unit_sphere = random_in_unit_sphere(sampler);
     if dot(in_unit_sphere, normal) > 0.0 {
         in_unit_sphere
     } else {
@@ -204,22 +212,30 @@ pub fn random_in_hemisphere(normal: Vec3) -> Vec3 {
     }
 }
 
-pub fn random_unit_vector() -> Vec3 {
-    unit_vector(random_in_unit_sphere())
+pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Vec3 {
+    let (u1, u2) = sampler.get_2d();
+    let z = 1.0 - 2.0 * u1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3 {
+        e: [r * phi.cos(), r * phi.sin(), z],
+    }
 }
 
-pub fn random_in_unit_disk() -> Vec3 {
-    loop {
-        let p = Vec3 {
-            e: [
-                random_double_lr(-1.0, 1.0),
-                random_double_lr(-1.0, 1.0),
-                0.0,
-            ],
-        };
-        if p.length_squared() < 1.0 {
-            break p;
-        }
+// Shirley-Chiu concentric mapping from the square to the disk
+pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Vec3 {
+    let (u1, u2) = sampler.get_2d();
+    let (a, b) = (2.0 * u1 - 1.0, 2.0 * u2 - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3 { e: [0.0; 3] };
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    Vec3 {
+        e: [r * theta.cos(), r * theta.sin(), 0.0],
     }
 }
 
@@ -19,13 +19,25 @@ impl Aabb {
     pub fn min(&self) -> Point3 {
         (*self).min
     }
+    pub fn area(&self) -> f64 {
+        let d = (*self).max - (*self).min;
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
     pub fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> bool {
+        let dir = r.get_dir();
+        let inv_dir = Vec3 {
+            e: [1.0 / dir.x(), 1.0 / dir.y(), 1.0 / dir.z()],
+        };
+        (*self).hit_inv(r.get_start(), inv_dir, t_min, t_max)
+    }
+    // same as `hit`, with the ray direction already inverted
+    pub fn hit_inv(&self, st: Point3, inv_dir: Vec3, t_min: f64, t_max: f64) -> bool {
         let mut t_min = t_min;
         let mut t_max = t_max;
         for i in 0..3 {
-            let invd = 1.0 / r.get_dir().e[i];
-            let mut t0 = ((*self).min.e[i] - r.get_start().e[i]) * invd;
-            let mut t1 = ((*self).max.e[i] - r.get_start().e[i]) * invd;
+            let invd = inv_dir.e[i];
+            let mut t0 = ((*self).min.e[i] - st.e[i]) * invd;
+            let mut t1 = ((*self).max.e[i] - st.e[i]) * invd;
             if invd < 0.0 {
                 std::mem::swap(&mut t0, &mut t1);
             }
@@ -0,0 +1,170 @@
+use super::aabb::{surrounding_box, Aabb};
+use crate::basic::{ray::Ray, vec3::Point3};
+use std::cmp::Ordering;
+use std::f64::INFINITY;
+
+// Bounds and total power of a group of lights.
+#[derive(Clone, Copy)]
+pub struct LightBounds {
+    pub bounds: Aabb,
+    pub power: f64,
+}
+
+impl LightBounds {
+    fn centroid(&self) -> Point3 {
+        ((*self).bounds.min() + (*self).bounds.max()) * 0.5
+    }
+
+    // Estimated contribution of the group at `p`: its power falling off with
+    // the squared distance, never taken closer than half the diagonal so that
+    // it stays finite for points inside the bounds.
+    fn importance(&self, p: Point3) -> f64 {
+        let d2 = (p - (*self).centroid()).length_squared();
+        let r2 = ((*self).bounds.max() - (*self).bounds.min()).length_squared() / 4.0;
+        (*self).power / d2.max(r2)
+    }
+}
+
+enum LightNodeKind {
+    // index of the light
+    Leaf(usize),
+    // indices of the two children
+    Interior(usize, usize),
+}
+
+struct LightNode {
+    light: LightBounds,
+    kind: LightNodeKind,
+}
+
+// Binary tree over the lights, walked from the root by picking either child
+// in proportion to its importance at the shading point. Lights close by are
+// then sampled much more often than the many far away ones, at the cost of
+// only log(#lights) steps per sample.
+pub struct LightBvh {
+    nodes: Vec<LightNode>,
+}
+
+impl LightBvh {
+    // Builds the tree over `lights`, which are referred to by their index.
+    pub fn creat(lights: &[LightBounds]) -> LightBvh {
+        let mut ret = LightBvh {
+            nodes: Default::default(),
+        };
+        if !lights.is_empty() {
+            ret.build(lights, (0..lights.len()).collect());
+        }
+        ret
+    }
+
+    fn build(&mut self, lights: &[LightBounds], mut ids: Vec<usize>) -> usize {
+        let id = (*self).nodes.len();
+        let mut light = lights[ids[0]];
+        for i in &ids[1..] {
+            light.bounds = surrounding_box(light.bounds, lights[*i].bounds);
+            light.power += lights[*i].power;
+        }
+        (*self).nodes.push(LightNode {
+            light,
+            kind: LightNodeKind::Leaf(ids[0]),
+        });
+        if ids.len() == 1 {
+            return id;
+        }
+
+        // halve along the axis where the centroids spread the most
+        let mut lo = lights[ids[0]].centroid();
+        let mut hi = lo;
+        for i in &ids {
+            let c = lights[*i].centroid();
+            for axis in 0..3 {
+                lo.e[axis] = lo.e[axis].min(c.e[axis]);
+                hi.e[axis] = hi.e[axis].max(c.e[axis]);
+            }
+        }
+        let extent = hi - lo;
+        let axis = if extent.x() > extent.y() && extent.x() > extent.z() {
+            0
+        } else if extent.y() > extent.z() {
+            1
+        } else {
+            2
+        };
+        ids.sort_by(|a, b| {
+            let ca = lights[*a].centroid().e[axis];
+            let cb = lights[*b].centroid().e[axis];
+            ca.partial_cmp(&cb).unwrap_or(Ordering::Equal)
+        });
+        let right = ids.split_off(ids.len() / 2);
+        let a = (*self).build(lights, ids);
+        let b = (*self).build(lights, right);
+        (*self).nodes[id].kind = LightNodeKind::Interior(a, b);
+        id
+    }
+
+    // Probability of going from an interior node to its first child `a`
+    // rather than to `b`.
+    fn probability(&self, a: usize, b: usize, p: Point3) -> f64 {
+        let ia = (*self).nodes[a].light.importance(p);
+        let ib = (*self).nodes[b].light.importance(p);
+        if ia + ib > 0.0 {
+            ia / (ia + ib)
+        } else {
+            0.5
+        }
+    }
+
+    // Picks a light for the shading point `p` with `u` in [0, 1).
+    pub fn sample(&self, p: Point3, u: f64) -> usize {
+        let mut u = u;
+        let mut node = 0;
+        loop {
+            match (*self).nodes[node].kind {
+                LightNodeKind::Leaf(light) => return light,
+                LightNodeKind::Interior(a, b) => {
+                    let pa = (*self).probability(a, b, p);
+                    if u < pa {
+                        u /= pa;
+                        node = a;
+                    } else {
+                        u = ((u - pa) / (1.0 - pa)).min(1.0 - f64::EPSILON);
+                        node = b;
+                    }
+                }
+            }
+        }
+    }
+
+    // Density of the direction of `r` when lights are picked with `sample`
+    // at its origin, given the density `light_pdf` of each light. Only the
+    // lights whose bounds `r` goes through are visited.
+    pub fn pdf(&self, r: Ray, light_pdf: &mut dyn FnMut(usize) -> f64) -> f64 {
+        if (*self).nodes.is_empty() {
+            0.0
+        } else {
+            (*self).pdf_from(0, 1.0, r, light_pdf)
+        }
+    }
+
+    fn pdf_from(
+        &self,
+        node: usize,
+        prob: f64,
+        r: Ray,
+        light_pdf: &mut dyn FnMut(usize) -> f64,
+    ) -> f64 {
+        match (*self).nodes[node].kind {
+            LightNodeKind::Leaf(light) => prob * light_pdf(light),
+            LightNodeKind::Interior(a, b) => {
+                let pa = (*self).probability(a, b, r.get_start());
+                let mut sum = 0.0;
+                for (child, pc) in [(a, pa), (b, 1.0 - pa)].iter() {
+                    if *pc > 0.0 && (*self).nodes[*child].light.bounds.hit(r, 0.0, INFINITY) {
+                        sum += (*self).pdf_from(*child, prob * pc, r, light_pdf);
+                    }
+                }
+                sum
+            }
+        }
+    }
+}
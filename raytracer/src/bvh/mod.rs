@@ -1,149 +1,412 @@
 pub mod aabb;
+pub mod light_bvh;
 
-use crate::basic::{ray::Ray, vec3::random_int_lr};
+use crate::basic::{
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
 use crate::hittable::{HitRecord, Hittable};
+use crate::sampler::Sampler;
 use aabb::{surrounding_box, Aabb};
 
 use std::cmp::Ordering;
+use std::f64::INFINITY;
 use std::process::exit;
 
-#[derive(Default)]
-pub struct BvhNode {
-    pub left: Option<Box<dyn Hittable>>,
-    pub right: Option<Box<dyn Hittable>>,
-    pub box_: Aabb,
+const SAH_BUCKETS: usize = 12;
+const MAX_LEAF_PRIMS: usize = 4;
+// past this depth nodes are halved, which bounds the tree depth by
+// MAX_SPLIT_DEPTH + log2(#objects) < STACK_SIZE
+const MAX_SPLIT_DEPTH: usize = 32;
+const STACK_SIZE: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplitMethod {
+    // split at the median object along the widest axis
+    Median,
+    // binned surface area heuristic
+    Sah,
 }
-impl Hittable for BvhNode {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        if !(*self).box_.hit(r, t_min, t_max) {
-            // eprintln!("OK");
-            None
-        } else {
-            let hit_left = if (*self).left.is_some() {
-                (*self).left.as_ref().unwrap().hit(r, t_min, t_max)
-            } else {
-                None
-            };
 
-            let hit_right = if (*self).right.is_some() {
-                (*self).right.as_ref().unwrap().hit(
-                    r,
-                    t_min,
-                    if hit_left.is_some() {
-                        hit_left.as_ref().unwrap().t
-                    } else {
-                        t_max
-                    },
-                )
-            } else {
-                None
-            };
+fn bucket_of(c: Point3, axis: usize, bounds: &Aabb) -> usize {
+    let extent = bounds.max().e[axis] - bounds.min().e[axis];
+    let b = ((c.e[axis] - bounds.min().e[axis]) / extent * SAH_BUCKETS as f64) as usize;
+    if b >= SAH_BUCKETS {
+        SAH_BUCKETS - 1
+    } else {
+        b
+    }
+}
 
-            if hit_right.is_some() {
-                hit_right
-            } else if hit_left.is_some() {
-                hit_left
-            } else {
-                None
-            }
+type Objects = Vec<Box<dyn Hittable>>;
+
+// The boxes of the objects, their centers and the box around the centers.
+fn centroids(objects: &[Box<dyn Hittable>], t0: f64, t1: f64) -> (Vec<Aabb>, Vec<Point3>, Aabb) {
+    let mut boxes: Vec<Aabb> = Default::default();
+    for obj in objects {
+        let mut b: Aabb = Default::default();
+        if !obj.bounding_box(t0, t1, &mut b) {
+            eprintln!("No bounding box in bvh_node constructor.");
+            exit(0);
         }
+        boxes.push(b);
     }
-    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut Aabb) -> bool {
-        *output_box = (*self).box_;
-        true
+    let centroids: Vec<Point3> = boxes.iter().map(|b| (b.min() + b.max()) * 0.5).collect();
+    let mut bounds = Aabb {
+        min: centroids[0],
+        max: centroids[0],
+    };
+    for c in &centroids {
+        bounds = surrounding_box(bounds, Aabb { min: *c, max: *c });
     }
+    (boxes, centroids, bounds)
 }
 
-fn box_compare(a: &dyn Hittable, b: &dyn Hittable, axis: usize) -> bool {
-    let mut box_a: Aabb = Default::default();
-    let mut box_b: Aabb = Default::default();
-    if !a.bounding_box(0.0, 0.0, &mut box_a) || !b.bounding_box(0.0, 0.0, &mut box_b) {
-        eprintln!("No bounding box in bvh_node constructor.");
-        exit(0);
+// Bins the object centroids along every axis and splits at the bucket boundary
+// with the lowest surface area cost. Falls back to halving the list when all
+// centroids coincide.
+fn sah_split(objects: Objects, t0: f64, t1: f64) -> (Objects, Objects) {
+    let (boxes, centroids, bounds) = centroids(&objects, t0, t1);
+
+    let mut best_cost = INFINITY;
+    let mut best_axis = 0;
+    let mut best_split = 0;
+    for axis in 0..3 {
+        if bounds.max().e[axis] - bounds.min().e[axis] <= 0.0 {
+            continue;
+        }
+        let mut counts = [0usize; SAH_BUCKETS];
+        let mut bucket_boxes: [Option<Aabb>; SAH_BUCKETS] = [None; SAH_BUCKETS];
+        for (b, c) in boxes.iter().zip(&centroids) {
+            let id = bucket_of(*c, axis, &bounds);
+            counts[id] += 1;
+            bucket_boxes[id] = Some(match bucket_boxes[id] {
+                Some(acc) => surrounding_box(acc, *b),
+                None => *b,
+            });
+        }
+        // sweep from the right so the left side can be accumulated on the fly
+        let mut right_area = [0.0; SAH_BUCKETS];
+        let mut right_count = [0usize; SAH_BUCKETS];
+        let mut acc: Option<Aabb> = None;
+        let mut n = 0;
+        for i in (1..SAH_BUCKETS).rev() {
+            if let Some(b) = bucket_boxes[i] {
+                acc = Some(acc.map_or(b, |a| surrounding_box(a, b)));
+            }
+            n += counts[i];
+            right_count[i - 1] = n;
+            right_area[i - 1] = acc.map_or(0.0, |a| a.area());
+        }
+        let mut acc: Option<Aabb> = None;
+        let mut n = 0;
+        for i in 0..SAH_BUCKETS - 1 {
+            if let Some(b) = bucket_boxes[i] {
+                acc = Some(acc.map_or(b, |a| surrounding_box(a, b)));
+            }
+            n += counts[i];
+            if n == 0 || right_count[i] == 0 {
+                continue;
+            }
+            let cost =
+                n as f64 * acc.map_or(0.0, |a| a.area()) + right_count[i] as f64 * right_area[i];
+            if cost < best_cost {
+                best_cost = cost;
+                best_axis = axis;
+                best_split = i;
+            }
+        }
+    }
+
+    let mut left: Objects = Default::default();
+    let mut right: Objects = Default::default();
+    if !best_cost.is_finite() {
+        let mut objects = objects;
+        let mid = objects.len() >> 1;
+        right = objects.split_off(mid);
+        left = objects;
+    } else {
+        for (obj, c) in objects.into_iter().zip(centroids) {
+            if bucket_of(c, best_axis, &bounds) <= best_split {
+                left.push(obj);
+            } else {
+                right.push(obj);
+            }
+        }
     }
-    box_a.min().e[axis] < box_b.min().e[axis]
+    (left, right)
 }
-#[allow(dead_code)]
-fn box_x_compare(a: &dyn Hittable, b: &dyn Hittable) -> bool {
-    box_compare(a, b, 0)
+
+// Sorts the objects along the axis their centroids spread the most over and
+// splits at the median one.
+fn median_split(objects: Objects, t0: f64, t1: f64) -> (Objects, Objects) {
+    let (_, centroids, bounds) = centroids(&objects, t0, t1);
+    let extent = bounds.max() - bounds.min();
+    let mut axis = 0;
+    for i in 1..3 {
+        if extent.e[i] > extent.e[axis] {
+            axis = i;
+        }
+    }
+    let mut sorted: Vec<(f64, Box<dyn Hittable>)> =
+        centroids.iter().map(|c| c.e[axis]).zip(objects).collect();
+    sorted.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+    let mut objects: Objects = sorted.into_iter().map(|(_, obj)| obj).collect();
+    let mid = objects.len() >> 1;
+    let right = objects.split_off(mid);
+    (objects, right)
 }
-#[allow(dead_code)]
-fn box_y_compare(a: &dyn Hittable, b: &dyn Hittable) -> bool {
-    box_compare(a, b, 1)
+
+// Leaves hold `count > 0` primitives starting at `offset`. Interior nodes have
+// `count == 0`, their first child right after them and the second at `offset`;
+// `left_below` tells whether the first child is the nearer one along `axis` for
+// rays going in the positive direction.
+#[derive(Default, Clone, Copy)]
+struct LinearNode {
+    box_: Aabb,
+    offset: usize,
+    count: usize,
+    axis: usize,
+    left_below: bool,
 }
-#[allow(dead_code)]
-fn box_z_compare(a: &dyn Hittable, b: &dyn Hittable) -> bool {
-    box_compare(a, b, 2)
+
+pub struct LinearBvh {
+    nodes: Vec<LinearNode>,
+    primitives: Objects,
 }
 
-impl BvhNode {
-    #[allow(dead_code)]
-    #[allow(clippy::ptr_arg)]
-    pub fn creat(src_objects: Vec<Box<dyn Hittable>>, t0: f64, t1: f64) -> BvhNode {
-        let mut objects = src_objects;
-        let axis = random_int_lr(0, 2);
-        let cmp = if axis == 0 {
-            box_x_compare
-        } else if axis == 1 {
-            box_y_compare
-        } else {
-            box_z_compare
+impl LinearBvh {
+    pub fn creat(
+        src_objects: Vec<Box<dyn Hittable>>,
+        t0: f64,
+        t1: f64,
+        split: SplitMethod,
+    ) -> LinearBvh {
+        let mut ret = LinearBvh {
+            nodes: Vec::with_capacity(2 * src_objects.len()),
+            primitives: Vec::with_capacity(src_objects.len()),
         };
-        let object_span = objects.len();
-        let mut ret: BvhNode = Default::default();
-        if object_span == 1 {
-            let a = objects.pop().unwrap();
-            ret.left = Some(a);
-            ret.right = None;
-        } else if object_span == 2 {
-            let b = objects.pop().unwrap();
-            let a = objects.pop().unwrap();
-            if cmp(&(*a), &(*b)) {
-                ret.left = Some(a);
-                ret.right = Some(b);
-            } else {
-                ret.left = Some(b);
-                ret.right = Some(a);
-            }
-        } else {
-            //sort()
-            objects.sort_by(|a, b| -> Ordering {
-                if cmp(&(**a), &(**b)) {
-                    Ordering::Less
-                } else if cmp(&(**b), &(**a)) {
-                    Ordering::Greater
-                } else {
-                    Ordering::Equal
+        if !src_objects.is_empty() {
+            ret.build(src_objects, t0, t1, split, 0);
+        }
+        ret
+    }
+
+    fn build(
+        &mut self,
+        objects: Objects,
+        t0: f64,
+        t1: f64,
+        split: SplitMethod,
+        depth: usize,
+    ) -> Aabb {
+        let id = (*self).nodes.len();
+        (*self).nodes.push(Default::default());
+
+        if objects.len() <= MAX_LEAF_PRIMS {
+            let mut box_: Option<Aabb> = None;
+            for obj in &objects {
+                let mut b: Aabb = Default::default();
+                if !obj.bounding_box(t0, t1, &mut b) {
+                    eprintln!("No bounding box in bvh_node constructor.");
+                    exit(0);
                 }
-            });
-            let mid = object_span >> 1;
+                box_ = Some(box_.map_or(b, |a| surrounding_box(a, b)));
+            }
+            (*self).nodes[id] = LinearNode {
+                box_: box_.unwrap(),
+                offset: (*self).primitives.len(),
+                count: objects.len(),
+                ..Default::default()
+            };
+            (*self).primitives.extend(objects);
+            return (*self).nodes[id].box_;
+        }
+
+        let (left_vec, right_vec) = if depth >= MAX_SPLIT_DEPTH {
+            // keep the traversal stack bounded on pathological inputs
             let mut left_vec = objects;
+            let mid = left_vec.len() >> 1;
             let right_vec = left_vec.split_off(mid);
+            (left_vec, right_vec)
+        } else {
+            match split {
+                SplitMethod::Median => median_split(objects, t0, t1),
+                SplitMethod::Sah => sah_split(objects, t0, t1),
+            }
+        };
+        let box_left = (*self).build(left_vec, t0, t1, split, depth + 1);
+        let offset = (*self).nodes.len();
+        let box_right = (*self).build(right_vec, t0, t1, split, depth + 1);
 
-            ret.left = Some(Box::new(BvhNode::creat(left_vec, t0, t1)));
-            ret.right = Some(Box::new(BvhNode::creat(right_vec, t0, t1)));
+        // children are visited nearest first along the axis separating them most
+        let d = (box_right.min() + box_right.max()) - (box_left.min() + box_left.max());
+        let mut axis = 0;
+        for i in 1..3 {
+            if d.e[i].abs() > d.e[axis].abs() {
+                axis = i;
+            }
         }
-        let mut box_left: Aabb = Default::default();
-        let mut box_right: Aabb = Default::default();
-        let fl = match ret.left.as_ref() {
-            Some(x) => x.bounding_box(t0, t1, &mut box_left),
-            None => false,
-        };
-        let fr = match ret.right.as_ref() {
-            Some(x) => x.bounding_box(t0, t1, &mut box_right),
-            None => false,
+        let box_ = surrounding_box(box_left, box_right);
+        (*self).nodes[id] = LinearNode {
+            box_,
+            offset,
+            count: 0,
+            axis,
+            left_below: d.e[axis] >= 0.0,
         };
-        if !fl && !fr {
-            eprintln!("No bounding box in bvh_node constructor.");
-            exit(0);
+        box_
+    }
+}
+
+impl Hittable for LinearBvh {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        if (*self).nodes.is_empty() {
+            return None;
         }
-        ret.box_ = if !fl {
-            surrounding_box(box_right, box_right)
-        } else if !fr {
-            surrounding_box(box_left, box_left)
-        } else {
-            surrounding_box(box_left, box_right)
+        let dir = r.get_dir();
+        let inv_dir = Vec3 {
+            e: [1.0 / dir.x(), 1.0 / dir.y(), 1.0 / dir.z()],
         };
+        let mut closest = t_max;
+        let mut ret = None;
+        let mut stack = [0usize; STACK_SIZE];
+        let mut top = 0;
+        let mut current = 0;
+        loop {
+            let node = &(*self).nodes[current];
+            if node.box_.hit_inv(r.get_start(), inv_dir, t_min, closest) {
+                if node.count > 0 {
+                    for obj in &(*self).primitives[node.offset..node.offset + node.count] {
+                        if let Some(rec) = obj.hit(r, t_min, closest, sampler) {
+                            closest = rec.t;
+                            ret = Some(rec);
+                        }
+                    }
+                } else {
+                    let (near, far) = if (dir.e[node.axis] < 0.0) == node.left_below {
+                        (node.offset, current + 1)
+                    } else {
+                        (current + 1, node.offset)
+                    };
+                    stack[top] = far;
+                    top += 1;
+                    current = near;
+                    continue;
+                }
+            }
+            if top == 0 {
+                break;
+            }
+            top -= 1;
+            current = stack[top];
+        }
         ret
     }
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut Aabb) -> bool {
+        match (*self).nodes.first() {
+            Some(node) => {
+                *output_box = node.box_;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::vec3::Color;
+    use crate::hittable::{hittable_list::HittableList, sphere::Sphere, triangle::Triangle};
+    use crate::material::{Lambertian, Material};
+    use crate::sampler::IndependentSampler;
+    use rand::prelude::*;
+    use std::sync::Arc;
+
+    fn point(rng: &mut StdRng, size: f64) -> Point3 {
+        Point3 {
+            e: [
+                rng.gen_range(-size..size),
+                rng.gen_range(-size..size),
+                rng.gen_range(-size..size),
+            ],
+        }
+    }
+
+    // Spheres and triangles, overlapping and of all sizes, the same for a seed.
+    fn objects(seed: u64) -> Objects {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mat: Arc<dyn Material> = Arc::new(Lambertian::creat(Color { e: [0.5; 3] }));
+        let mut objects: Objects = Default::default();
+        for _ in 0..300 {
+            objects.push(Box::new(Sphere {
+                center: point(&mut rng, 10.0),
+                radius: rng.gen_range(0.05..1.5),
+                mat_ptr: mat.clone(),
+            }));
+            let v0 = point(&mut rng, 10.0);
+            objects.push(Box::new(Triangle::creat(
+                v0,
+                v0 + point(&mut rng, 2.0),
+                v0 + point(&mut rng, 2.0),
+                mat.clone(),
+            )));
+        }
+        objects
+    }
+
+    fn assert_same_hits(bvh: &LinearBvh, list: &HittableList, rays: usize) {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut sampler = IndependentSampler::creat(0);
+        let mut hits = 0;
+        for _ in 0..rays {
+            // from around the objects towards a point among them
+            let st = point(&mut rng, 15.0);
+            let r = Ray {
+                st,
+                dir: point(&mut rng, 5.0) - st,
+                tm: 0.0,
+            };
+            let t_max = if rng.gen_bool(0.5) { INFINITY } else { 8.0 };
+            let expected = list.hit(r, 0.001, t_max, &mut sampler).map(|rec| rec.t);
+            let got = bvh.hit(r, 0.001, t_max, &mut sampler).map(|rec| rec.t);
+            assert_eq!(got, expected, "ray {:?} {:?}", r.get_start(), r.get_dir());
+            hits += got.is_some() as usize;
+        }
+        // most rays must hit something for the comparison to mean anything
+        assert!(hits > rays / 10, "only {} of {} rays hit", hits, rays);
+    }
+
+    #[test]
+    fn linear_bvh_finds_the_closest_hit_of_the_list() {
+        for split in [SplitMethod::Sah, SplitMethod::Median] {
+            let bvh = LinearBvh::creat(objects(1), 0.0, 1.0, split);
+            let list = HittableList {
+                objects: objects(1),
+            };
+            assert_same_hits(&bvh, &list, 20000);
+        }
+    }
+
+    #[test]
+    fn coincident_centroids_still_build_a_valid_tree() {
+        let mat: Arc<dyn Material> = Arc::new(Lambertian::creat(Color { e: [0.5; 3] }));
+        let spheres = || -> Objects {
+            (0..100)
+                .map(|i| -> Box<dyn Hittable> {
+                    Box::new(Sphere {
+                        center: Point3 { e: [0.0; 3] },
+                        radius: 0.1 + i as f64 * 0.05,
+                        mat_ptr: mat.clone(),
+                    })
+                })
+                .collect()
+        };
+        for split in [SplitMethod::Sah, SplitMethod::Median] {
+            let bvh = LinearBvh::creat(spheres(), 0.0, 1.0, split);
+            let list = HittableList { objects: spheres() };
+            assert_same_hits(&bvh, &list, 2000);
+        }
+    }
 }
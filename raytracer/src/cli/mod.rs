@@ -0,0 +1,410 @@
+use crate::bvh::SplitMethod;
+use crate::film::{Filter, FilterKind};
+use crate::integrator::IntegratorKind;
+use crate::light::LightSelection;
+use crate::output::{
+    tonemap::{ToneMap, ToneMapping},
+    Format,
+};
+use crate::sampler::SamplerKind;
+use clap::{crate_version, value_t, App, Arg, Error, ErrorKind};
+use std::path::Path;
+use std::time::Duration;
+
+pub struct Progressive {
+    pub time_limit: Option<Duration>,
+    pub preview: Option<String>,
+}
+
+pub struct Adaptive {
+    // relative standard error below which a pixel stops taking samples
+    pub threshold: f64,
+    pub max_spp: Option<usize>,
+    pub heatmap: Option<(String, Format)>,
+}
+
+pub struct Options {
+    pub scene: String,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub samples_per_pixel: Option<usize>,
+    pub max_depth: isize,
+    pub roulette_depth: isize,
+    pub integrator: IntegratorKind,
+    pub ao_distance: Option<f64>,
+    pub light_selection: LightSelection,
+    pub threads: usize,
+    pub output: String,
+    pub format: Format,
+    pub tone_mapping: ToneMapping,
+    pub filter: Filter,
+    pub sampler: SamplerKind,
+    pub seed: Option<u64>,
+    pub split: SplitMethod,
+    pub progressive: Option<Progressive>,
+    pub pass_spp: usize,
+    pub adaptive: Option<Adaptive>,
+}
+
+fn positive(v: String) -> Result<(), String> {
+    match v.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(()),
+        _ => Err(format!("expected a positive integer, got `{}`", v)),
+    }
+}
+
+fn positive_number(v: String) -> Result<(), String> {
+    match v.parse::<f64>() {
+        Ok(n) if n > 0.0 && n.is_finite() => Ok(()),
+        _ => Err(format!("expected a positive number, got `{}`", v)),
+    }
+}
+
+fn number(v: String) -> Result<(), String> {
+    match v.parse::<f64>() {
+        Ok(n) if n.is_finite() => Ok(()),
+        _ => Err(format!("expected a number, got `{}`", v)),
+    }
+}
+
+fn quality(v: String) -> Result<(), String> {
+    match v.parse::<u8>() {
+        Ok(n) if (1..=100).contains(&n) => Ok(()),
+        _ => Err(format!("expected a number between 1 and 100, got `{}`", v)),
+    }
+}
+
+fn format_of(path: &str, format: Option<&str>, quality: u8) -> Format {
+    let ext = match format {
+        Some(ext) => Some(ext),
+        None => Path::new(path).extension().and_then(|e| e.to_str()),
+    };
+    match ext.and_then(|ext| Format::from_extension(ext, quality)) {
+        Some(format) => format,
+        None => Error::with_description(
+            &format!("cannot tell the image format of `{}`, pass --format", path),
+            ErrorKind::InvalidValue,
+        )
+        .exit(),
+    }
+}
+
+pub fn parse() -> Options {
+    let matches = App::new("raytracer")
+        .version(crate_version!())
+        .about("Renders a built-in scene or a TOML scene file")
+        .arg(
+            Arg::with_name("scene")
+                .short("S")
+                .long("scene")
+                .value_name("SCENE")
+                .default_value("cornell_box")
+                .help("Built-in scene (cornell_box, final_scene) or path to a scene file"),
+        )
+        .arg(
+            Arg::with_name("width")
+                .short("W")
+                .long("width")
+                .value_name("PIXELS")
+                .validator(positive)
+                .help("Image width, defaults to the scene's"),
+        )
+        .arg(
+            Arg::with_name("height")
+                .short("H")
+                .long("height")
+                .value_name("PIXELS")
+                .validator(positive)
+                .help("Image height, defaults to the scene's"),
+        )
+        .arg(
+            Arg::with_name("spp")
+                .short("s")
+                .long("spp")
+                .value_name("N")
+                .validator(positive)
+                .help("Samples per pixel, defaults to the scene's"),
+        )
+        .arg(
+            Arg::with_name("max-depth")
+                .short("d")
+                .long("max-depth")
+                .value_name("N")
+                .default_value("50")
+                .validator(positive)
+                .help("Maximum number of bounces per path"),
+        )
+        .arg(
+            Arg::with_name("rr-depth")
+                .long("rr-depth")
+                .value_name("N")
+                .default_value("3")
+                .validator(positive)
+                .help("Bounces before Russian roulette may end a path"),
+        )
+        .arg(
+            Arg::with_name("integrator")
+                .short("i")
+                .long("integrator")
+                .value_name("INTEGRATOR")
+                .possible_values(&["path", "nee", "normals", "albedo", "ao"])
+                .default_value("path")
+                .help("What to render: path tracing, path tracing with next event estimation, or a debug view of the first hit"),
+        )
+        .arg(
+            Arg::with_name("ao-distance")
+                .long("ao-distance")
+                .value_name("DISTANCE")
+                .validator(positive_number)
+                .help("Occlusion range of the ao integrator, a tenth of the scene's extent by default"),
+        )
+        .arg(
+            Arg::with_name("light-selection")
+                .long("light-selection")
+                .value_name("STRATEGY")
+                .possible_values(&["uniform", "power", "bvh", "auto"])
+                .default_value("auto")
+                .help("How a light is picked for sampling: uniformly, by power, or by a light BVH estimating its contribution; auto uses the BVH once there are many lights"),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .short("j")
+                .long("threads")
+                .value_name("N")
+                .default_value("32")
+                .validator(positive)
+                .help("Number of render threads"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .default_value("output/output.jpg")
+                .help("Output image path"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .short("f")
+                .long("format")
+                .value_name("FORMAT")
+                .possible_values(&["jpeg", "png", "bmp", "tga", "pfm", "hdr", "exr"])
+                .help("Output format, guessed from the output extension by default"),
+        )
+        .arg(
+            Arg::with_name("quality")
+                .short("q")
+                .long("quality")
+                .value_name("1-100")
+                .default_value("100")
+                .validator(quality)
+                .help("JPEG quality"),
+        )
+        .arg(
+            Arg::with_name("tonemap")
+                .long("tonemap")
+                .value_name("OPERATOR")
+                .possible_values(&["clamp", "reinhard", "extended-reinhard", "aces"])
+                .default_value("clamp")
+                .help("Tone mapping operator for 8-bit outputs"),
+        )
+        .arg(
+            Arg::with_name("exposure")
+                .long("exposure")
+                .value_name("STOPS")
+                .default_value("0")
+                .validator(number)
+                .allow_hyphen_values(true)
+                .help("Exposure adjustment applied before tone mapping"),
+        )
+        .arg(
+            Arg::with_name("white")
+                .long("white")
+                .value_name("LUMINANCE")
+                .default_value("4")
+                .validator(positive_number)
+                .help("Luminance mapped to white by extended-reinhard"),
+        )
+        .arg(
+            Arg::with_name("sampler")
+                .long("sampler")
+                .value_name("SAMPLER")
+                .possible_values(&["independent", "stratified", "halton", "sobol"])
+                .default_value("sobol")
+                .help("Sample generator, sobol works best with a power of two spp"),
+        )
+        .arg(
+            Arg::with_name("filter")
+                .long("filter")
+                .value_name("FILTER")
+                .possible_values(&["box", "tent", "gaussian", "mitchell", "lanczos"])
+                .default_value("box")
+                .help("Pixel reconstruction filter"),
+        )
+        .arg(
+            Arg::with_name("filter-radius")
+                .long("filter-radius")
+                .value_name("PIXELS")
+                .validator(positive_number)
+                .help("Filter radius in pixels, each filter has its own default"),
+        )
+        .arg(
+            Arg::with_name("bvh")
+                .long("bvh")
+                .value_name("METHOD")
+                .possible_values(&["sah", "median"])
+                .default_value("sah")
+                .help("How BVH nodes are split"),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("SEED")
+                .help("Seed for the scene and the per-pixel sample streams, random by default"),
+        )
+        .arg(
+            Arg::with_name("progressive")
+                .short("p")
+                .long("progressive")
+                .help("Render in passes, rewriting the image after each one"),
+        )
+        .arg(
+            Arg::with_name("pass-spp")
+                .long("pass-spp")
+                .value_name("N")
+                .default_value("16")
+                .validator(positive)
+                .help("Samples per pixel added by each progressive or adaptive pass"),
+        )
+        .arg(
+            Arg::with_name("time-limit")
+                .long("time-limit")
+                .value_name("SECONDS")
+                .validator(positive_number)
+                .requires("progressive")
+                .help("Time budget for progressive rendering, no pass is started that would overrun it"),
+        )
+        .arg(
+            Arg::with_name("preview")
+                .long("preview")
+                .value_name("FILE")
+                .requires("progressive")
+                .help("Write the image after each pass here instead of to the output"),
+        )
+        .arg(
+            Arg::with_name("adaptive")
+                .long("adaptive")
+                .value_name("ERROR")
+                .validator(positive_number)
+                .help("Stop sampling pixels once their relative error is below ERROR, spp becomes the average budget"),
+        )
+        .arg(
+            Arg::with_name("max-spp")
+                .long("max-spp")
+                .value_name("N")
+                .validator(positive)
+                .requires("adaptive")
+                .help("Samples per pixel an adaptive render may spend on one pixel, 4 times spp by default"),
+        )
+        .arg(
+            Arg::with_name("heatmap")
+                .long("heatmap")
+                .value_name("FILE")
+                .requires("adaptive")
+                .help("Also write the number of samples taken in each pixel as an image"),
+        )
+        .get_matches();
+
+    let output = matches.value_of("output").unwrap().to_string();
+    let quality = value_t!(matches, "quality", u8).unwrap_or_else(|e| e.exit());
+    let format = format_of(&output, matches.value_of("format"), quality);
+
+    Options {
+        scene: matches.value_of("scene").unwrap().to_string(),
+        width: value_t!(matches, "width", usize).ok(),
+        height: value_t!(matches, "height", usize).ok(),
+        samples_per_pixel: value_t!(matches, "spp", usize).ok(),
+        max_depth: value_t!(matches, "max-depth", isize).unwrap_or_else(|e| e.exit()),
+        roulette_depth: value_t!(matches, "rr-depth", isize).unwrap_or_else(|e| e.exit()),
+        integrator: match matches.value_of("integrator") {
+            Some("nee") => IntegratorKind::Nee,
+            Some("normals") => IntegratorKind::Normals,
+            Some("albedo") => IntegratorKind::Albedo,
+            Some("ao") => IntegratorKind::Ao,
+            _ => IntegratorKind::Path,
+        },
+        ao_distance: value_t!(matches, "ao-distance", f64).ok(),
+        light_selection: match matches.value_of("light-selection") {
+            Some("uniform") => LightSelection::Uniform,
+            Some("power") => LightSelection::Power,
+            Some("bvh") => LightSelection::Bvh,
+            _ => LightSelection::Auto,
+        },
+        threads: value_t!(matches, "threads", usize).unwrap_or_else(|e| e.exit()),
+        output,
+        format,
+        tone_mapping: ToneMapping {
+            operator: match matches.value_of("tonemap") {
+                Some("reinhard") => ToneMap::Reinhard,
+                Some("extended-reinhard") => ToneMap::ExtendedReinhard(
+                    value_t!(matches, "white", f64).unwrap_or_else(|e| e.exit()),
+                ),
+                Some("aces") => ToneMap::Aces,
+                _ => ToneMap::Clamp,
+            },
+            exposure: value_t!(matches, "exposure", f64).unwrap_or_else(|e| e.exit()),
+        },
+        filter: {
+            let kind = match matches.value_of("filter") {
+                Some("tent") => FilterKind::Tent,
+                Some("gaussian") => FilterKind::Gaussian,
+                Some("mitchell") => FilterKind::Mitchell,
+                Some("lanczos") => FilterKind::Lanczos,
+                _ => FilterKind::Box,
+            };
+            Filter {
+                kind,
+                radius: value_t!(matches, "filter-radius", f64)
+                    .unwrap_or_else(|_| kind.default_radius()),
+            }
+        },
+        sampler: match matches.value_of("sampler") {
+            Some("independent") => SamplerKind::Independent,
+            Some("stratified") => SamplerKind::Stratified,
+            Some("halton") => SamplerKind::Halton,
+            _ => SamplerKind::Sobol,
+        },
+        seed: if matches.is_present("seed") {
+            Some(value_t!(matches, "seed", u64).unwrap_or_else(|e| e.exit()))
+        } else {
+            None
+        },
+        split: match matches.value_of("bvh") {
+            Some("median") => SplitMethod::Median,
+            _ => SplitMethod::Sah,
+        },
+        progressive: if matches.is_present("progressive") {
+            Some(Progressive {
+                time_limit: value_t!(matches, "time-limit", f64)
+                    .ok()
+                    .map(Duration::from_secs_f64),
+                preview: matches.value_of("preview").map(|s| s.to_string()),
+            })
+        } else {
+            None
+        },
+        pass_spp: value_t!(matches, "pass-spp", usize).unwrap_or_else(|e| e.exit()),
+        adaptive: if matches.is_present("adaptive") {
+            Some(Adaptive {
+                threshold: value_t!(matches, "adaptive", f64).unwrap_or_else(|e| e.exit()),
+                max_spp: value_t!(matches, "max-spp", usize).ok(),
+                heatmap: matches.value_of("heatmap").map(|path| {
+                    // only the image itself was asked for with --format
+                    (path.to_string(), format_of(path, None, quality))
+                }),
+            })
+        } else {
+            None
+        },
+    }
+}
@@ -0,0 +1,359 @@
+use crate::basic::vec3::Color;
+use crate::output::tonemap::luminance;
+use std::f64::consts::PI;
+use std::f64::INFINITY;
+
+// below this mean luminance the error is taken relative to it instead, so that
+// nearly black pixels don't need an absurd number of samples
+const MIN_RELATIVE_LUMINANCE: f64 = 0.01;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    // Mitchell-Netravali with B = C = 1/3
+    Mitchell,
+    // windowed sinc with three lobes
+    Lanczos,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Filter {
+    pub kind: FilterKind,
+    // in pixels, along each axis
+    pub radius: f64,
+}
+
+impl FilterKind {
+    pub fn default_radius(&self) -> f64 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+fn mitchell(x: f64) -> f64 {
+    let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+    let x = x.abs();
+    if x < 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+            + (-18.0 + 12.0 * b + 6.0 * c) * x.powi(2)
+            + (6.0 - 2.0 * b))
+            / 6.0
+    } else if x < 2.0 {
+        ((-b - 6.0 * c) * x.powi(3)
+            + (6.0 * b + 30.0 * c) * x.powi(2)
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        0.0
+    }
+}
+
+impl Filter {
+    // the filter is separable, this is its profile along one axis
+    fn eval_1d(&self, x: f64) -> f64 {
+        let r = (*self).radius;
+        if x.abs() > r {
+            return 0.0;
+        }
+        match (*self).kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => r - x.abs(),
+            FilterKind::Gaussian => {
+                // sigma = radius / 3, shifted so the filter reaches zero at the radius
+                let alpha = 4.5 / (r * r);
+                (-alpha * x * x).exp() - (-alpha * r * r).exp()
+            }
+            FilterKind::Mitchell => mitchell(2.0 * x / r),
+            FilterKind::Lanczos => {
+                let t = x / r;
+                sinc(3.0 * t) * sinc(t)
+            }
+        }
+    }
+}
+
+// Luminance statistics of the samples taken inside one pixel, before any
+// filtering, used to tell when the pixel has converged.
+#[derive(Clone, Copy, Default)]
+struct PixelStats {
+    count: usize,
+    sum: f64,
+    sum_sq: f64,
+}
+
+// Weighted sums of the samples splatted onto rows `y0..y0 + rows` of an image
+// `width` pixels wide and `height` high. Pixel (x, y) covers [x, x + 1) x
+// [y, y + 1) in sample space.
+#[derive(Clone)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pub filter: Filter,
+    y0: usize,
+    rows: usize,
+    sums: Vec<Color>,
+    weights: Vec<f64>,
+    stats: Vec<PixelStats>,
+    scratch: Vec<f64>,
+}
+
+impl Film {
+    pub fn creat(width: usize, height: usize, filter: Filter) -> Film {
+        Film::creat_rows(width, height, filter, 0, height)
+    }
+
+    fn creat_rows(width: usize, height: usize, filter: Filter, y0: usize, rows: usize) -> Film {
+        Film {
+            width,
+            height,
+            filter,
+            y0,
+            rows,
+            sums: vec![Color { e: [0.0; 3] }; width * rows],
+            weights: vec![0.0; width * rows],
+            stats: vec![Default::default(); width * rows],
+            scratch: Default::default(),
+        }
+    }
+
+    // An empty film covering every pixel that samples taken in scanline `y`
+    // can reach.
+    pub fn creat_scanline(width: usize, height: usize, filter: Filter, y: usize) -> Film {
+        let reach = (filter.radius - 0.5).ceil().max(0.0) as usize;
+        let y0 = y.saturating_sub(reach);
+        let y1 = (y + reach + 1).min(height);
+        Film::creat_rows(width, height, filter, y0, y1 - y0)
+    }
+
+    pub fn add_sample(&mut self, px: f64, py: f64, color: Color) {
+        // a NaN or infinite sample would spoil every pixel it reaches for good
+        if !color.e.iter().all(|c| c.is_finite()) {
+            return;
+        }
+        let l = luminance(color);
+        let index = (py as usize - (*self).y0) * (*self).width + px as usize;
+        let stats = &mut (*self).stats[index];
+        stats.count += 1;
+        stats.sum += l;
+        stats.sum_sq += l * l;
+        // pixels whose center is in (p - r, p + r], so that with a box of
+        // radius 1/2 every sample lands in exactly one pixel
+        let r = (*self).filter.radius;
+        let x0 = ((px - r - 0.5).floor() as isize + 1).max(0);
+        let x1 = ((px + r - 0.5).floor() as isize).min((*self).width as isize - 1);
+        let y0 = ((py - r - 0.5).floor() as isize + 1).max((*self).y0 as isize);
+        let y1 = ((py + r - 0.5).floor() as isize).min(((*self).y0 + (*self).rows) as isize - 1);
+        // the filter is separable, evaluate each column once
+        (*self).scratch.clear();
+        for x in x0..=x1 {
+            let fx = (*self).filter.eval_1d(x as f64 + 0.5 - px);
+            (*self).scratch.push(fx);
+        }
+        for y in y0..=y1 {
+            let wy = (*self).filter.eval_1d(y as f64 + 0.5 - py);
+            if wy == 0.0 {
+                continue;
+            }
+            let row = (y as usize - (*self).y0) * (*self).width;
+            for (x, fx) in (x0..=x1).zip(&(*self).scratch) {
+                let w = fx * wy;
+                if w != 0.0 {
+                    (*self).sums[row + x as usize] += color * w;
+                    (*self).weights[row + x as usize] += w;
+                }
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: &Film) {
+        let offset = (other.y0 - (*self).y0) * (*self).width;
+        for i in 0..other.sums.len() {
+            (*self).sums[offset + i] += other.sums[i];
+            (*self).weights[offset + i] += other.weights[i];
+            let stats = &mut (*self).stats[offset + i];
+            stats.count += other.stats[i].count;
+            stats.sum += other.stats[i].sum;
+            stats.sum_sq += other.stats[i].sum_sq;
+        }
+    }
+
+    // Samples taken inside each pixel, in the same order as `image`.
+    pub fn sample_counts(&self) -> Vec<usize> {
+        (*self).stats.iter().map(|s| s.count).collect()
+    }
+
+    // Standard error of the pixel's mean luminance relative to that mean.
+    pub fn relative_error(&self, x: usize, y: usize) -> f64 {
+        let stats = (*self).stats[(y - (*self).y0) * (*self).width + x];
+        if stats.count < 2 {
+            return INFINITY;
+        }
+        let n = stats.count as f64;
+        let mean = stats.sum / n;
+        let variance = ((stats.sum_sq - stats.sum * mean) / (n - 1.0)).max(0.0);
+        (variance / n).sqrt() / mean.max(MIN_RELATIVE_LUMINANCE)
+    }
+
+    // The filtered image in scanline order, bottom row first.
+    pub fn image(&self) -> Vec<Color> {
+        (*self)
+            .sums
+            .iter()
+            .zip(&(*self).weights)
+            .map(|(c, w)| {
+                // the negative lobes of Mitchell and Lanczos can leave a
+                // pixel with a weight below zero, the quotient is still its
+                // estimate; only the ringing below black is cut
+                let mut pixel = Color { e: [0.0; 3] };
+                if *w != 0.0 {
+                    pixel = *c / *w;
+                    for v in pixel.e.iter_mut() {
+                        *v = v.max(0.0);
+                    }
+                }
+                pixel
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [FilterKind; 5] = [
+        FilterKind::Box,
+        FilterKind::Tent,
+        FilterKind::Gaussian,
+        FilterKind::Mitchell,
+        FilterKind::Lanczos,
+    ];
+
+    fn filter(kind: FilterKind) -> Filter {
+        Filter {
+            kind,
+            radius: kind.default_radius(),
+        }
+    }
+
+    #[test]
+    fn constant_samples_give_a_constant_image() {
+        let color = Color { e: [0.3, 0.5, 0.7] };
+        for kind in KINDS.iter() {
+            let mut film = Film::creat(6, 5, filter(*kind));
+            for y in 0..5 * 8 {
+                for x in 0..6 * 8 {
+                    film.add_sample((x as f64 + 0.5) / 8.0, (y as f64 + 0.5) / 8.0, color);
+                }
+            }
+            for pixel in film.image() {
+                for c in 0..3 {
+                    assert!((pixel.e[c] - color.e[c]).abs() < 1e-9, "{:?}", kind);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn filters_integrate_to_a_positive_weight() {
+        for kind in KINDS.iter() {
+            let f = filter(*kind);
+            let n = 10000;
+            let dx = 2.0 * f.radius / n as f64;
+            let integral: f64 = (0..n)
+                .map(|i| f.eval_1d(-f.radius + (i as f64 + 0.5) * dx) * dx)
+                .sum();
+            assert!(integral > 0.0, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn negative_weight_pixels_are_not_black() {
+        // the pixel right of the sample only sees Mitchell's negative lobe
+        let mut film = Film::creat(4, 1, filter(FilterKind::Mitchell));
+        let color = Color { e: [0.5; 3] };
+        film.add_sample(0.0, 0.5, color);
+        assert!(film.weights[1] < 0.0);
+        assert!((film.image()[1].e[0] - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn non_finite_samples_are_dropped() {
+        let mut film = Film::creat(3, 3, filter(FilterKind::Gaussian));
+        film.add_sample(1.5, 1.5, Color { e: [1.0; 3] });
+        film.add_sample(
+            1.2,
+            1.7,
+            Color {
+                e: [f64::NAN, 0.0, 0.0],
+            },
+        );
+        film.add_sample(
+            1.7,
+            1.2,
+            Color {
+                e: [f64::INFINITY; 3],
+            },
+        );
+        for pixel in film.image() {
+            assert!(pixel.e.iter().all(|c| (c - 1.0).abs() < 1e-9));
+        }
+        assert_eq!(film.sample_counts()[4], 1);
+    }
+
+    #[test]
+    fn relative_error_is_the_standard_error_over_the_mean() {
+        let mut film = Film::creat(3, 1, filter(FilterKind::Box));
+        let gray = |v: f64| Color { e: [v; 3] };
+        for i in 0..16 {
+            // a flat pixel, one alternating between 0 and 2, and a dark one
+            film.add_sample(0.5, 0.5, gray(1.0));
+            film.add_sample(1.5, 0.5, gray((i % 2 * 2) as f64));
+            film.add_sample(2.5, 0.5, gray((i % 2) as f64 * 0.002));
+        }
+        assert!(film.relative_error(0, 0) < 1e-9);
+        // variance 16/15, over 16 samples, of a mean of 1
+        let expected = (16.0 / 15.0 / 16.0f64).sqrt();
+        assert!((film.relative_error(1, 0) - expected).abs() < 1e-9);
+        // below the floor the error is relative to the floor, not the mean
+        let dark = expected * 0.001 / MIN_RELATIVE_LUMINANCE;
+        assert!((film.relative_error(2, 0) - dark).abs() < 1e-9);
+    }
+
+    #[test]
+    fn relative_error_shrinks_with_more_samples() {
+        let mut film = Film::creat(1, 1, filter(FilterKind::Box));
+        assert!(film.relative_error(0, 0).is_infinite());
+        let mut errors = vec![];
+        for i in 0..256 {
+            let color = Color {
+                e: [(i % 4) as f64; 3],
+            };
+            film.add_sample(0.5, 0.5, color);
+            if (i + 1) % 64 == 0 {
+                errors.push(film.relative_error(0, 0));
+            }
+        }
+        // the standard error falls with the square root of the sample count
+        for w in errors.windows(2) {
+            assert!(w[1] < w[0]);
+        }
+        assert!((errors[0] / errors[3] - 2.0).abs() < 0.05);
+    }
+}
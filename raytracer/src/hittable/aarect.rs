@@ -1,11 +1,13 @@
-use crate::hittable::{HitRecord, Hittable};
+use crate::hittable::{diffuse_power, HitRecord, Hittable};
 
 use crate::basic::{
     ray::Ray,
-    vec3::{Point3, Vec3},
+    vec3::{dot, Point3, Vec3},
 };
 use crate::bvh::aabb::Aabb;
 use crate::material::Material;
+use crate::sampler::{NullSampler, Sampler};
+use std::f64::INFINITY;
 
 #[derive(Clone)]
 pub struct XyRect<T: Material> {
@@ -18,7 +20,7 @@ pub struct XyRect<T: Material> {
 }
 #[allow(clippy::many_single_char_names)]
 impl<T: Material> Hittable for XyRect<T> {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, _sampler: &mut dyn Sampler) -> Option<HitRecord> {
         let t = ((*self).k - r.get_start().z()) / r.get_dir().z();
         if t < t_min || t > t_max {
             None
@@ -31,7 +33,8 @@ impl<T: Material> Hittable for XyRect<T> {
                 let u = (x - (*self).x0) / ((*self).x1 - (*self).x0);
                 let v = (y - (*self).y0) / ((*self).y1 - (*self).y0);
                 let outward_normal = Vec3 { e: [0.0, 0.0, 1.0] };
-                let rec = HitRecord::creat(u, v, t, outward_normal, r, r.at(t), &(*self).mp);
+                let mut rec = HitRecord::creat(u, v, t, outward_normal, r, r.at(t), &(*self).mp);
+                rec.tangent = Vec3 { e: [1.0, 0.0, 0.0] };
                 Some(rec)
             }
         }
@@ -47,6 +50,41 @@ impl<T: Material> Hittable for XyRect<T> {
         };
         true
     }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        match (*self).hit(
+            Ray {
+                st: o,
+                dir: v,
+                tm: 0.0,
+            },
+            0.001,
+            INFINITY,
+            &mut NullSampler,
+        ) {
+            Some(rec) => {
+                let area = ((*self).x1 - (*self).x0) * ((*self).y1 - (*self).y0);
+                let distance_squared = rec.t.powi(2) * v.length_squared();
+                let cosine = (dot(v, rec.normal) / v.length()).abs();
+                distance_squared / (cosine * area)
+            }
+            None => 0.0,
+        }
+    }
+    fn random(&self, o: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (s, t) = sampler.get_2d();
+        let random_point = Point3 {
+            e: [
+                (*self).x0 + s * ((*self).x1 - (*self).x0),
+                (*self).y0 + t * ((*self).y1 - (*self).y0),
+                (*self).k,
+            ],
+        };
+        random_point - o
+    }
+    fn power(&self) -> f64 {
+        let area = ((*self).x1 - (*self).x0) * ((*self).y1 - (*self).y0);
+        diffuse_power(area, &(*self).mp)
+    }
 }
 
 #[derive(Clone)]
@@ -61,7 +99,7 @@ pub struct XzRect<T> {
 
 #[allow(clippy::many_single_char_names)]
 impl<T: Material> Hittable for XzRect<T> {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, _sampler: &mut dyn Sampler) -> Option<HitRecord> {
         let t = ((*self).k - r.get_start().y()) / r.get_dir().y();
         if t < t_min || t > t_max {
             None
@@ -74,7 +112,8 @@ impl<T: Material> Hittable for XzRect<T> {
                 let u = (x - (*self).x0) / ((*self).x1 - (*self).x0);
                 let v = (z - (*self).z0) / ((*self).z1 - (*self).z0);
                 let outward_normal = Vec3 { e: [0.0, 1.0, 0.0] };
-                let rec = HitRecord::creat(u, v, t, outward_normal, r, r.at(t), &(*self).mp);
+                let mut rec = HitRecord::creat(u, v, t, outward_normal, r, r.at(t), &(*self).mp);
+                rec.tangent = Vec3 { e: [1.0, 0.0, 0.0] };
                 Some(rec)
             }
         }
@@ -90,6 +129,41 @@ impl<T: Material> Hittable for XzRect<T> {
         };
         true
     }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        match (*self).hit(
+            Ray {
+                st: o,
+                dir: v,
+                tm: 0.0,
+            },
+            0.001,
+            INFINITY,
+            &mut NullSampler,
+        ) {
+            Some(rec) => {
+                let area = ((*self).x1 - (*self).x0) * ((*self).z1 - (*self).z0);
+                let distance_squared = rec.t.powi(2) * v.length_squared();
+                let cosine = (dot(v, rec.normal) / v.length()).abs();
+                distance_squared / (cosine * area)
+            }
+            None => 0.0,
+        }
+    }
+    fn random(&self, o: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (s, t) = sampler.get_2d();
+        let random_point = Point3 {
+            e: [
+                (*self).x0 + s * ((*self).x1 - (*self).x0),
+                (*self).k,
+                (*self).z0 + t * ((*self).z1 - (*self).z0),
+            ],
+        };
+        random_point - o
+    }
+    fn power(&self) -> f64 {
+        let area = ((*self).x1 - (*self).x0) * ((*self).z1 - (*self).z0);
+        diffuse_power(area, &(*self).mp)
+    }
 }
 
 #[derive(Clone)]
@@ -104,7 +178,7 @@ pub struct YzRect<T> {
 
 #[allow(clippy::many_single_char_names)]
 impl<T: Material> Hittable for YzRect<T> {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, _sampler: &mut dyn Sampler) -> Option<HitRecord> {
         let t = ((*self).k - r.get_start().x()) / r.get_dir().x();
         if t < t_min || t > t_max {
             None
@@ -117,7 +191,8 @@ impl<T: Material> Hittable for YzRect<T> {
                 let u = (y - (*self).y0) / ((*self).y1 - (*self).y0);
                 let v = (z - (*self).z0) / ((*self).z1 - (*self).z0);
                 let outward_normal = Vec3 { e: [1.0, 0.0, 0.0] };
-                let rec = HitRecord::creat(u, v, t, outward_normal, r, r.at(t), &(*self).mp);
+                let mut rec = HitRecord::creat(u, v, t, outward_normal, r, r.at(t), &(*self).mp);
+                rec.tangent = Vec3 { e: [0.0, 1.0, 0.0] };
                 Some(rec)
             }
         }
@@ -133,4 +208,39 @@ impl<T: Material> Hittable for YzRect<T> {
         };
         true
     }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        match (*self).hit(
+            Ray {
+                st: o,
+                dir: v,
+                tm: 0.0,
+            },
+            0.001,
+            INFINITY,
+            &mut NullSampler,
+        ) {
+            Some(rec) => {
+                let area = ((*self).y1 - (*self).y0) * ((*self).z1 - (*self).z0);
+                let distance_squared = rec.t.powi(2) * v.length_squared();
+                let cosine = (dot(v, rec.normal) / v.length()).abs();
+                distance_squared / (cosine * area)
+            }
+            None => 0.0,
+        }
+    }
+    fn random(&self, o: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (s, t) = sampler.get_2d();
+        let random_point = Point3 {
+            e: [
+                (*self).k,
+                (*self).y0 + s * ((*self).y1 - (*self).y0),
+                (*self).z0 + t * ((*self).z1 - (*self).z0),
+            ],
+        };
+        random_point - o
+    }
+    fn power(&self) -> f64 {
+        let area = ((*self).y1 - (*self).y0) * ((*self).z1 - (*self).z0);
+        diffuse_power(area, &(*self).mp)
+    }
 }
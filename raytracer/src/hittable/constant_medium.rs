@@ -3,10 +3,11 @@ use std::f64::INFINITY;
 use super::{HitRecord, Hittable};
 use crate::basic::{
     ray::Ray,
-    vec3::{random_double, Color, Vec3},
+    vec3::{Color, Vec3},
 };
 use crate::bvh::aabb::Aabb;
 use crate::material::{Isotropic, Material};
+use crate::sampler::Sampler;
 use crate::texture::{SolidColor, Texture};
 
 #[derive(Clone)]
@@ -41,14 +42,14 @@ impl<T: Hittable> ConstantMedium<T, Isotropic<SolidColor>> {
 
 #[allow(clippy::question_mark)]
 impl<T: Hittable, U: Material> Hittable for ConstantMedium<T, U> {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let rec1 = (*self).boundary.hit(r, -INFINITY, INFINITY);
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let rec1 = (*self).boundary.hit(r, -INFINITY, INFINITY, sampler);
         if rec1.is_none() {
             return None;
         }
         let mut rec1 = rec1.unwrap();
 
-        let rec2 = (*self).boundary.hit(r, rec1.t + 0.0001, INFINITY);
+        let rec2 = (*self).boundary.hit(r, rec1.t + 0.0001, INFINITY, sampler);
         if rec2.is_none() {
             return None;
         }
@@ -71,7 +72,7 @@ impl<T: Hittable, U: Material> Hittable for ConstantMedium<T, U> {
 
         let ray_length = r.get_dir().length();
         let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
-        let hit_distance = (*self).neg_inv_density * random_double().ln();
+        let hit_distance = (*self).neg_inv_density * sampler.get_1d().ln();
 
         if hit_distance > distance_inside_boundary {
             return None;
@@ -87,6 +88,7 @@ impl<T: Hittable, U: Material> Hittable for ConstantMedium<T, U> {
 
             u: 0.0,
             v: 0.0,
+            tangent: Vec3 { e: [0.0; 3] },
         };
         Some(rec)
     }
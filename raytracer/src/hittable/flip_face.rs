@@ -1,6 +1,10 @@
 use super::{HitRecord, Hittable};
-use crate::basic::ray::Ray;
+use crate::basic::{
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
 use crate::bvh::aabb::Aabb;
+use crate::sampler::Sampler;
 
 #[derive(Clone)]
 pub struct FlipFace<T: Hittable> {
@@ -9,8 +13,8 @@ pub struct FlipFace<T: Hittable> {
 
 #[allow(clippy::unnecessary_unwrap)]
 impl<T: Hittable> Hittable for FlipFace<T> {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let rec = (*self).ptr.hit(r, t_min, t_max);
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let rec = (*self).ptr.hit(r, t_min, t_max, sampler);
         if rec.is_none() {
             None
         } else {
@@ -23,4 +27,16 @@ impl<T: Hittable> Hittable for FlipFace<T> {
     fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut Aabb) -> bool {
         (*self).ptr.bounding_box(t0, t1, output_box)
     }
+
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        (*self).ptr.pdf_value(o, v)
+    }
+
+    fn random(&self, o: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        (*self).ptr.random(o, sampler)
+    }
+
+    fn power(&self) -> f64 {
+        (*self).ptr.power()
+    }
 }
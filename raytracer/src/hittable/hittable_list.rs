@@ -1,6 +1,10 @@
 use super::{HitRecord, Hittable};
-use crate::basic::ray::Ray;
+use crate::basic::{
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
 use crate::bvh::aabb::{surrounding_box, Aabb};
+use crate::sampler::Sampler;
 #[derive(Default)]
 pub struct HittableList {
     pub objects: Vec<Box<dyn Hittable>>,
@@ -18,11 +22,11 @@ impl HittableList {
 
 #[allow(clippy::unnecessary_unwrap)]
 impl Hittable for HittableList {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
         let mut closest_so_far = t_max;
         let mut ret: Option<HitRecord> = Default::default();
         for object in &(*self).objects {
-            let rec = object.hit(r, t_min, closest_so_far);
+            let rec = object.hit(r, t_min, closest_so_far, sampler);
             if rec.is_some() {
                 let tmp = rec.unwrap();
                 closest_so_far = tmp.t;
@@ -52,4 +56,25 @@ impl Hittable for HittableList {
             true
         }
     }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        if (*self).objects.is_empty() {
+            return 0.0;
+        }
+        let weight = 1.0 / (*self).objects.len() as f64;
+        let mut sum = 0.0;
+        for obj in &(*self).objects {
+            sum += weight * obj.pdf_value(o, v);
+        }
+        sum
+    }
+
+    fn random(&self, o: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let size = (*self).objects.len();
+        let id = ((sampler.get_1d() * size as f64) as usize).min(size - 1);
+        (*self).objects[id].random(o, sampler)
+    }
+
+    fn power(&self) -> f64 {
+        (*self).objects.iter().map(|obj| obj.power()).sum()
+    }
 }
@@ -0,0 +1,345 @@
+use super::triangle::Triangle;
+use super::{HitRecord, Hittable};
+use crate::basic::{
+    ray::Ray,
+    vec3::{cross, Color, Point3, Vec3},
+};
+use crate::bvh::{aabb::Aabb, LinearBvh, SplitMethod};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::sampler::Sampler;
+use crate::texture::{ImageTexture, SolidColor, Texture};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum MeshError {
+    Io(PathBuf, std::io::Error),
+    Parse {
+        file: PathBuf,
+        line: usize,
+        msg: String,
+    },
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MeshError::Io(path, e) => write!(f, "cannot read {}: {}", path.display(), e),
+            MeshError::Parse { file, line, msg } => {
+                write!(f, "{}:{}: {}", file.display(), line, msg)
+            }
+        }
+    }
+}
+
+pub struct TriangleMesh {
+    pub bvh: LinearBvh,
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        (*self).bvh.hit(r, t_min, t_max, sampler)
+    }
+    fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut Aabb) -> bool {
+        (*self).bvh.bounding_box(t0, t1, output_box)
+    }
+}
+
+struct Parser<'a> {
+    file: &'a Path,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, msg: String) -> Result<T, MeshError> {
+        Err(MeshError::Parse {
+            file: (*self).file.to_path_buf(),
+            line: (*self).line,
+            msg,
+        })
+    }
+
+    fn floats(&self, args: &[&str], min: usize, max: usize) -> Result<Vec<f64>, MeshError> {
+        if args.len() < min || args.len() > max {
+            return (*self).error(format!(
+                "expected {} to {} numbers, got {}",
+                min,
+                max,
+                args.len()
+            ));
+        }
+        let mut ret = Vec::with_capacity(args.len());
+        for a in args {
+            match a.parse::<f64>() {
+                Ok(x) => ret.push(x),
+                Err(_) => return (*self).error(format!("invalid number `{}`", a)),
+            }
+        }
+        Ok(ret)
+    }
+
+    fn color(&self, args: &[&str]) -> Result<Color, MeshError> {
+        let c = (*self).floats(args, 1, 3)?;
+        Ok(if c.len() == 3 {
+            Color {
+                e: [c[0], c[1], c[2]],
+            }
+        } else {
+            Color { e: [c[0]; 3] }
+        })
+    }
+
+    // OBJ indices are 1-based, negative ones count back from the latest element.
+    fn index(&self, s: &str, len: usize, what: &str) -> Result<usize, MeshError> {
+        let i = match s.parse::<isize>() {
+            Ok(i) => i,
+            Err(_) => return (*self).error(format!("invalid {} index `{}`", what, s)),
+        };
+        let idx = if i > 0 { i - 1 } else { len as isize + i };
+        if i == 0 || idx < 0 || idx >= len as isize {
+            (*self).error(format!("{} index {} out of range", what, i))
+        } else {
+            Ok(idx as usize)
+        }
+    }
+}
+
+#[derive(Default)]
+struct MtlDef {
+    kd: Option<Color>,
+    ks: Option<Color>,
+    ke: Option<Color>,
+    ns: Option<f64>,
+    ni: Option<f64>,
+    dissolve: Option<f64>,
+    illum: Option<usize>,
+    map_kd: Option<PathBuf>,
+}
+
+impl MtlDef {
+    // Maps the Wavefront illumination model onto our materials: transparent or
+    // refractive models become `Dielectric`, reflective ones `Metal` (fuzz taken
+    // from the specular exponent), emissive ones `DiffuseLight` and everything
+    // else `Lambertian`.
+    fn build(&self) -> Result<Arc<dyn Material>, String> {
+        if let Some(ke) = (*self).ke {
+            if !ke.near_zero() {
+                return Ok(Arc::new(DiffuseLight::creat_color(ke)));
+            }
+        }
+        let illum = (*self).illum.unwrap_or(2);
+        if matches!(illum, 4 | 6 | 7 | 9) || matches!((*self).dissolve, Some(d) if d < 1.0) {
+            return Ok(Arc::new(Dielectric {
+                ir: (*self).ni.unwrap_or(1.5),
+            }));
+        }
+        if matches!(illum, 3 | 5 | 8) {
+            let albedo = (*self).ks.or((*self).kd).unwrap_or(Color { e: [1.0; 3] });
+            let fuzz = 1.0 - (*self).ns.unwrap_or(1000.0).clamp(0.0, 1000.0) / 1000.0;
+            return Ok(Arc::new(Metal::creat(albedo, fuzz)));
+        }
+        let albedo: Arc<dyn Texture> = match &(*self).map_kd {
+            Some(path) => match ImageTexture::open(&path.to_string_lossy()) {
+                Ok(tex) => Arc::new(tex),
+                Err(e) => return Err(format!("cannot load image {}: {}", path.display(), e)),
+            },
+            None => Arc::new(SolidColor {
+                color_value: (*self).kd.unwrap_or(Color { e: [0.8; 3] }),
+            }),
+        };
+        Ok(Arc::new(Lambertian { albedo }))
+    }
+}
+
+fn load_mtl(path: &Path) -> Result<HashMap<String, Arc<dyn Material>>, MeshError> {
+    let src = match fs::read_to_string(path) {
+        Ok(src) => src,
+        Err(e) => return Err(MeshError::Io(path.to_path_buf(), e)),
+    };
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut parser = Parser {
+        file: path,
+        line: 0,
+    };
+    let mut defs: Vec<(String, usize, MtlDef)> = Default::default();
+    for (i, line) in src.lines().enumerate() {
+        parser.line = i + 1;
+        let mut tokens = line.split_whitespace();
+        let key = match tokens.next() {
+            Some(key) if !key.starts_with('#') => key,
+            _ => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+        if key == "newmtl" {
+            if args.len() != 1 {
+                return parser.error("expected a material name".to_string());
+            }
+            defs.push((args[0].to_string(), parser.line, Default::default()));
+            continue;
+        }
+        let def = match defs.last_mut() {
+            Some((_, _, def)) => def,
+            None => return parser.error(format!("`{}` before any `newmtl`", key)),
+        };
+        match key {
+            "Kd" => def.kd = Some(parser.color(&args)?),
+            "Ks" => def.ks = Some(parser.color(&args)?),
+            "Ke" => def.ke = Some(parser.color(&args)?),
+            "Ns" => def.ns = Some(parser.floats(&args, 1, 1)?[0]),
+            "Ni" => def.ni = Some(parser.floats(&args, 1, 1)?[0]),
+            "d" => def.dissolve = Some(parser.floats(&args, 1, 1)?[0]),
+            "Tr" => def.dissolve = Some(1.0 - parser.floats(&args, 1, 1)?[0]),
+            "illum" => def.illum = Some(parser.floats(&args, 1, 1)?[0] as usize),
+            "map_Kd" => match args.last() {
+                Some(file) => def.map_kd = Some(dir.join(file)),
+                None => return parser.error("expected a texture file".to_string()),
+            },
+            // other statements (Ka, maps we can't use, ...) are ignored
+            _ => {}
+        }
+    }
+
+    let mut ret: HashMap<String, Arc<dyn Material>> = Default::default();
+    for (name, line, def) in defs {
+        parser.line = line;
+        match def.build() {
+            Ok(mat) => {
+                ret.insert(name, mat);
+            }
+            Err(msg) => return parser.error(format!("material `{}`: {}", name, msg)),
+        }
+    }
+    Ok(ret)
+}
+
+impl TriangleMesh {
+    // Loads the `v`/`vt`/`vn`/`f` records of a Wavefront OBJ file. Faces take
+    // their material from `usemtl` (through the `mtllib` files) and fall back to
+    // `default_mat`; polygons with more than three vertices are fanned.
+    pub fn load_obj(
+        file: &str,
+        default_mat: Option<Arc<dyn Material>>,
+        scale: f64,
+        split: SplitMethod,
+    ) -> Result<TriangleMesh, MeshError> {
+        let path = Path::new(file);
+        let src = match fs::read_to_string(path) {
+            Ok(src) => src,
+            Err(e) => return Err(MeshError::Io(path.to_path_buf(), e)),
+        };
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut parser = Parser {
+            file: path,
+            line: 0,
+        };
+
+        let mut positions: Vec<Point3> = Default::default();
+        let mut texcoords: Vec<[f64; 2]> = Default::default();
+        let mut normals: Vec<Vec3> = Default::default();
+        let mut materials: HashMap<String, Arc<dyn Material>> = Default::default();
+        let mut current = default_mat.clone();
+        let mut triangles: Vec<Box<dyn Hittable>> = Default::default();
+
+        for (i, line) in src.lines().enumerate() {
+            parser.line = i + 1;
+            let mut tokens = line.split_whitespace();
+            let key = match tokens.next() {
+                Some(key) if !key.starts_with('#') => key,
+                _ => continue,
+            };
+            let args: Vec<&str> = tokens.collect();
+            match key {
+                "v" => {
+                    let p = parser.floats(&args, 3, 4)?;
+                    positions.push(Point3 {
+                        e: [p[0] * scale, p[1] * scale, p[2] * scale],
+                    });
+                }
+                "vt" => {
+                    let t = parser.floats(&args, 1, 3)?;
+                    texcoords.push([t[0], if t.len() > 1 { t[1] } else { 0.0 }]);
+                }
+                "vn" => {
+                    let n = parser.floats(&args, 3, 3)?;
+                    normals.push(Vec3 {
+                        e: [n[0], n[1], n[2]],
+                    });
+                }
+                "mtllib" => {
+                    for lib in &args {
+                        materials.extend(load_mtl(&dir.join(lib))?);
+                    }
+                }
+                "usemtl" => {
+                    current = match args.first().and_then(|name| materials.get(*name)) {
+                        Some(mat) => Some(mat.clone()),
+                        None if args.is_empty() => default_mat.clone(),
+                        None => return parser.error(format!("unknown material `{}`", args[0])),
+                    };
+                }
+                "f" => {
+                    if args.len() < 3 {
+                        return parser.error("a face needs at least three vertices".to_string());
+                    }
+                    let mat = match &current {
+                        Some(mat) => mat.clone(),
+                        None => {
+                            return parser
+                                .error("face has no material and no default was given".to_string())
+                        }
+                    };
+                    let mut verts: Vec<(Point3, Option<[f64; 2]>, Option<Vec3>)> =
+                        Default::default();
+                    for a in &args {
+                        let mut parts = a.split('/');
+                        let p = parser.index(parts.next().unwrap(), positions.len(), "vertex")?;
+                        let t = match parts.next() {
+                            Some(s) if !s.is_empty() => {
+                                Some(texcoords[parser.index(s, texcoords.len(), "texture")?])
+                            }
+                            _ => None,
+                        };
+                        let n = match parts.next() {
+                            Some(s) if !s.is_empty() => {
+                                Some(normals[parser.index(s, normals.len(), "normal")?])
+                            }
+                            _ => None,
+                        };
+                        verts.push((positions[p], t, n));
+                    }
+                    for k in 1..verts.len() - 1 {
+                        let (a, b, c) = (verts[0], verts[k], verts[k + 1]);
+                        if cross(b.0 - a.0, c.0 - a.0).length_squared() <= 0.0 {
+                            continue;
+                        }
+                        let mut tri = Triangle::creat(a.0, b.0, c.0, mat.clone());
+                        if let (Some(ta), Some(tb), Some(tc)) = (a.1, b.1, c.1) {
+                            tri.uvs = Some([ta, tb, tc]);
+                        }
+                        if let (Some(na), Some(nb), Some(nc)) = (a.2, b.2, c.2) {
+                            tri.normals = Some([na, nb, nc]);
+                        }
+                        triangles.push(Box::new(tri));
+                    }
+                }
+                // groups, objects and smoothing groups carry no geometry
+                _ => {}
+            }
+        }
+
+        if triangles.is_empty() {
+            return Err(MeshError::Parse {
+                file: path.to_path_buf(),
+                line: parser.line,
+                msg: "no faces found".to_string(),
+            });
+        }
+        Ok(TriangleMesh {
+            bvh: LinearBvh::creat(triangles, 0.0, 1.0, split),
+        })
+    }
+}
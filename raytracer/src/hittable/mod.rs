@@ -2,18 +2,25 @@ pub mod aarect;
 pub mod constant_medium;
 pub mod flip_face;
 pub mod hittable_list;
+pub mod mesh;
 pub mod moving_sphere;
 pub mod mybox;
 pub mod rotate_y;
 pub mod sphere;
 pub mod translate;
+pub mod triangle;
 
 use crate::basic::{
+    onb::Onb,
     ray::Ray,
     vec3::{dot, Point3, Vec3},
 };
 use crate::bvh::aabb::Aabb;
 use crate::material::Material;
+use crate::output::tonemap::luminance;
+use crate::sampler::Sampler;
+use std::f64::consts::PI;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct HitRecord<'a> {
@@ -24,6 +31,8 @@ pub struct HitRecord<'a> {
     pub v: f64,
     pub front_face: bool,
     pub mat_ptr: &'a dyn Material,
+    // direction in which u grows, zero for shapes without one
+    pub tangent: Vec3,
 }
 
 impl<'a> HitRecord<'a> {
@@ -54,13 +63,76 @@ impl<'a> HitRecord<'a> {
             normal: Vec3 { e: [0.0; 3] },
             front_face: false,
             mat_ptr: mat_ptr,
+            tangent: Vec3 { e: [0.0; 3] },
         };
         ret.set_face_normal(r, outward_normal);
         ret
     }
+    // Frame around the normal whose u axis follows the surface's tangent, so
+    // that anisotropic materials stay aligned with the surface.
+    pub fn shading_frame(&self) -> Onb {
+        let mut uvw: Onb = Default::default();
+        uvw.build_from_w_u((*self).normal, (*self).tangent);
+        uvw
+    }
 }
 
 pub trait Hittable: Send + Sync {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord>;
     fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut Aabb) -> bool;
+    // The three below are only for the shapes that can be sampled as lights.
+    // Any other shape in a light list is a bug, so the defaults panic instead
+    // of quietly giving a wrong estimate.
+    fn pdf_value(&self, _o: Point3, _v: Vec3) -> f64 {
+        panic!("this hittable cannot be sampled as a light")
+    }
+    fn random(&self, _o: Point3, _sampler: &mut dyn Sampler) -> Vec3 {
+        panic!("this hittable cannot be sampled as a light")
+    }
+    // Emitted power (as luminance), used to pick among the lights
+    fn power(&self) -> f64 {
+        panic!("this hittable cannot be sampled as a light")
+    }
+}
+
+// Power of a diffuse emitter of the given area: its radiance integrated over
+// the cosine weighted hemisphere of every point.
+pub fn diffuse_power(area: f64, mat: &dyn Material) -> f64 {
+    PI * area * luminance(mat.emission())
+}
+
+impl Hittable for Box<dyn Hittable> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        (**self).hit(r, t_min, t_max, sampler)
+    }
+    fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut Aabb) -> bool {
+        (**self).bounding_box(t0, t1, output_box)
+    }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        (**self).pdf_value(o, v)
+    }
+    fn random(&self, o: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        (**self).random(o, sampler)
+    }
+    fn power(&self) -> f64 {
+        (**self).power()
+    }
+}
+
+impl Hittable for Arc<dyn Hittable> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        (**self).hit(r, t_min, t_max, sampler)
+    }
+    fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut Aabb) -> bool {
+        (**self).bounding_box(t0, t1, output_box)
+    }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        (**self).pdf_value(o, v)
+    }
+    fn random(&self, o: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        (**self).random(o, sampler)
+    }
+    fn power(&self) -> f64 {
+        (**self).power()
+    }
 }
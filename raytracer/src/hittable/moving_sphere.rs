@@ -3,8 +3,9 @@ use crate::basic::{
     vec3::{dot, Point3},
 };
 use crate::bvh::aabb::{surrounding_box, Aabb};
-use crate::hittable::{HitRecord, Hittable};
+use crate::hittable::{sphere::sphere_tangent, HitRecord, Hittable};
 use crate::material::Material;
+use crate::sampler::Sampler;
 
 #[derive(Clone)]
 pub struct MovingSphere<T: Material> {
@@ -25,7 +26,7 @@ impl<T: Material> MovingSphere<T> {
 }
 
 impl<T: Material> Hittable for MovingSphere<T> {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, _sampler: &mut dyn Sampler) -> Option<HitRecord> {
         let oc = r.get_start() - (*self).center(r.get_time());
         let a = r.get_dir().length_squared();
         let half_b = dot(oc, r.get_dir());
@@ -42,15 +43,17 @@ impl<T: Material> Hittable for MovingSphere<T> {
                     return None;
                 }
             }
-            let rec = HitRecord::creat(
+            let outward_normal = (r.at(root) - (*self).center(r.get_time())) / (*self).radius;
+            let mut rec = HitRecord::creat(
                 0.0,
                 0.0,
                 root,
-                (r.at(root) - (*self).center(r.get_time())) / (*self).radius,
+                outward_normal,
                 r,
                 r.at(root),
                 &(*self).mat_ptr,
             );
+            rec.tangent = sphere_tangent(outward_normal);
             Some(rec)
         }
     }
@@ -4,6 +4,7 @@ use crate::basic::{ray::Ray, vec3::Point3};
 use crate::bvh::aabb::Aabb;
 use crate::hittable::{HitRecord, Hittable};
 use crate::material::Material;
+use crate::sampler::Sampler;
 
 pub struct MyBox {
     pub box_min: Point3,
@@ -77,8 +78,8 @@ impl MyBox {
 }
 
 impl Hittable for MyBox {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        (*self).sides.hit(r, t_min, t_max)
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        (*self).sides.hit(r, t_min, t_max, sampler)
     }
     fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut Aabb) -> bool {
         *output_box = Aabb {
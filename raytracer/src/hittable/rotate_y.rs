@@ -5,6 +5,7 @@ use crate::basic::{
     {degrees_to_radians, fmax, fmin},
 };
 use crate::bvh::aabb::Aabb;
+use crate::sampler::Sampler;
 use std::f64::INFINITY;
 
 #[derive(Clone)]
@@ -60,9 +61,30 @@ impl<T: Hittable> RotateY<T> {
         }
     }
 }
+impl<T: Hittable> RotateY<T> {
+    fn to_object(&self, v: Vec3) -> Vec3 {
+        Vec3 {
+            e: [
+                (*self).cos_theta * v.e[0] - (*self).sin_theta * v.e[2],
+                v.e[1],
+                (*self).sin_theta * v.e[0] + (*self).cos_theta * v.e[2],
+            ],
+        }
+    }
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        Vec3 {
+            e: [
+                (*self).cos_theta * v.e[0] + (*self).sin_theta * v.e[2],
+                v.e[1],
+                (*self).cos_theta * v.e[2] - (*self).sin_theta * v.e[0],
+            ],
+        }
+    }
+}
+
 #[allow(clippy::unnecessary_unwrap)]
 impl<T: Hittable> Hittable for RotateY<T> {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
         let mut origin = r.get_start();
         let mut dir_ = r.get_dir();
 
@@ -79,7 +101,7 @@ impl<T: Hittable> Hittable for RotateY<T> {
             dir: dir_,
             tm: r.get_time(),
         };
-        let tmp = (*self).ptr.hit(rotate_r, t_min, t_max);
+        let tmp = (*self).ptr.hit(rotate_r, t_min, t_max, sampler);
         if tmp.is_none() {
             None
         } else {
@@ -93,6 +115,7 @@ impl<T: Hittable> Hittable for RotateY<T> {
             normal.e[2] = (*self).cos_theta * rec.normal.e[2] - (*self).sin_theta * rec.normal.e[0];
 
             rec.p = p;
+            rec.tangent = (*self).to_world(rec.tangent);
             rec.set_face_normal(rotate_r, normal);
 
             Some(rec)
@@ -102,4 +125,15 @@ impl<T: Hittable> Hittable for RotateY<T> {
         *output_box = (*self).bbox;
         true
     }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        (*self)
+            .ptr
+            .pdf_value((*self).to_object(o), (*self).to_object(v))
+    }
+    fn random(&self, o: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        (*self).to_world((*self).ptr.random((*self).to_object(o), sampler))
+    }
+    fn power(&self) -> f64 {
+        (*self).ptr.power()
+    }
 }
@@ -1,11 +1,14 @@
 use crate::basic::{
+    onb::Onb,
     ray::Ray,
-    vec3::{dot, Point3},
+    vec3::{dot, Point3, Vec3},
 };
 use crate::bvh::aabb::Aabb;
-use crate::hittable::{HitRecord, Hittable};
+use crate::hittable::{diffuse_power, HitRecord, Hittable};
 use crate::material::Material;
-use std::f64::consts::PI;
+use crate::pdf::random_to_sphere;
+use crate::sampler::{NullSampler, Sampler};
+use std::f64::{consts::PI, INFINITY};
 
 #[derive(Default, Clone)]
 pub struct Sphere<T: Material + Clone> {
@@ -21,8 +24,16 @@ pub fn get_sphere_uv(p: Point3, u: &mut f64, v: &mut f64) {
     *v = theta / PI;
 }
 
+// Derivative of the point along u (the longitude of `get_sphere_uv`) at unit
+// normal `p`, zero at the poles.
+pub fn sphere_tangent(p: Vec3) -> Vec3 {
+    Vec3 {
+        e: [p.z(), 0.0, -p.x()],
+    }
+}
+
 impl<T: Material + Clone> Hittable for Sphere<T> {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, _sampler: &mut dyn Sampler) -> Option<HitRecord> {
         let oc = r.get_start() - (*self).center;
         let a = r.get_dir().length_squared();
         let half_b = dot(oc, r.get_dir());
@@ -43,7 +54,7 @@ impl<T: Material + Clone> Hittable for Sphere<T> {
             let mut u_ = 0.0;
             let mut v_ = 0.0;
             get_sphere_uv(outward_normal_, &mut u_, &mut v_);
-            let rec = HitRecord::creat(
+            let mut rec = HitRecord::creat(
                 u_,
                 v_,
                 root,
@@ -52,6 +63,7 @@ impl<T: Material + Clone> Hittable for Sphere<T> {
                 r.at(root),
                 &(*self).mat_ptr,
             );
+            rec.tangent = sphere_tangent(outward_normal_);
             Some(rec)
         }
     }
@@ -68,4 +80,37 @@ impl<T: Material + Clone> Hittable for Sphere<T> {
         };
         true
     }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        match (*self).hit(
+            Ray {
+                st: o,
+                dir: v,
+                tm: 0.0,
+            },
+            0.001,
+            INFINITY,
+            &mut NullSampler,
+        ) {
+            Some(_) => {
+                // `o` may lie on the sphere when it is also what scatters
+                let cos_theta_max = (1.0
+                    - (*self).radius * (*self).radius / ((*self).center - o).length_squared())
+                .max(0.0)
+                .sqrt();
+                let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+                1.0 / solid_angle
+            }
+            None => 0.0,
+        }
+    }
+    fn random(&self, o: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let direction = (*self).center - o;
+        let distance_squared = direction.length_squared();
+        let mut uvw: Onb = Default::default();
+        uvw.build_from_w(direction);
+        uvw.local_vec(random_to_sphere((*self).radius, distance_squared, sampler))
+    }
+    fn power(&self) -> f64 {
+        diffuse_power(4.0 * PI * (*self).radius * (*self).radius, &(*self).mat_ptr)
+    }
 }
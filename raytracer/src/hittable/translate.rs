@@ -1,6 +1,10 @@
 use super::{HitRecord, Hittable};
-use crate::basic::{ray::Ray, vec3::Vec3};
+use crate::basic::{
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
 use crate::bvh::aabb::Aabb;
+use crate::sampler::Sampler;
 
 #[derive(Clone)]
 pub struct Translate<T: Hittable> {
@@ -9,13 +13,13 @@ pub struct Translate<T: Hittable> {
 }
 #[allow(clippy::unnecessary_unwrap)]
 impl<T: Hittable> Hittable for Translate<T> {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
         let moved_r = Ray {
             st: r.get_start() - (*self).offset,
             dir: r.get_dir(),
             tm: r.get_time(),
         };
-        let rec = (*self).ptr.hit(moved_r, t_min, t_max);
+        let rec = (*self).ptr.hit(moved_r, t_min, t_max, sampler);
         if rec.is_none() {
             None
         } else {
@@ -36,4 +40,13 @@ impl<T: Hittable> Hittable for Translate<T> {
             true
         }
     }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        (*self).ptr.pdf_value(o - (*self).offset, v)
+    }
+    fn random(&self, o: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        (*self).ptr.random(o - (*self).offset, sampler)
+    }
+    fn power(&self) -> f64 {
+        (*self).ptr.power()
+    }
 }
@@ -0,0 +1,155 @@
+use crate::basic::{
+    ray::Ray,
+    vec3::{cross, dot, unit_vector, Point3, Vec3},
+    {fmax, fmin},
+};
+use crate::bvh::aabb::Aabb;
+use crate::hittable::{diffuse_power, HitRecord, Hittable};
+use crate::material::Material;
+use crate::sampler::{NullSampler, Sampler};
+use std::f64::INFINITY;
+
+#[derive(Clone)]
+pub struct Triangle<T: Material> {
+    pub v: [Point3; 3],
+    pub normals: Option<[Vec3; 3]>,
+    pub uvs: Option<[[f64; 2]; 3]>,
+    pub mat_ptr: T,
+}
+
+impl<T: Material> Triangle<T> {
+    pub fn creat(v0: Point3, v1: Point3, v2: Point3, mat_ptr: T) -> Triangle<T> {
+        Triangle {
+            v: [v0, v1, v2],
+            normals: None,
+            uvs: None,
+            mat_ptr,
+        }
+    }
+
+    // Direction in which the texture's u grows, the first edge without uvs.
+    fn dpdu(&self) -> Vec3 {
+        let e1 = (*self).v[1] - (*self).v[0];
+        let e2 = (*self).v[2] - (*self).v[0];
+        if let Some(uv) = &(*self).uvs {
+            let (du1, dv1) = (uv[1][0] - uv[0][0], uv[1][1] - uv[0][1]);
+            let (du2, dv2) = (uv[2][0] - uv[0][0], uv[2][1] - uv[0][1]);
+            let det = du1 * dv2 - dv1 * du2;
+            if det.abs() > 1e-12 {
+                return (e1 * dv2 - e2 * dv1) / det;
+            }
+        }
+        e1
+    }
+
+    pub fn area(&self) -> f64 {
+        cross((*self).v[1] - (*self).v[0], (*self).v[2] - (*self).v[0]).length() / 2.0
+    }
+}
+
+#[allow(clippy::many_single_char_names)]
+impl<T: Material> Hittable for Triangle<T> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, _sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        // Möller–Trumbore
+        let e1 = (*self).v[1] - (*self).v[0];
+        let e2 = (*self).v[2] - (*self).v[0];
+        let p = cross(r.get_dir(), e2);
+        let det = dot(e1, p);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let s = r.get_start() - (*self).v[0];
+        let b1 = dot(s, p) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+        let q = cross(s, e1);
+        let b2 = dot(r.get_dir(), q) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+        let t = dot(e2, q) * inv_det;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let b0 = 1.0 - b1 - b2;
+
+        let (u, v) = match &(*self).uvs {
+            Some(uv) => (
+                b0 * uv[0][0] + b1 * uv[1][0] + b2 * uv[2][0],
+                b0 * uv[0][1] + b1 * uv[1][1] + b2 * uv[2][1],
+            ),
+            None => (b1, b2),
+        };
+        let geometric = unit_vector(cross(e1, e2));
+        let mut rec = HitRecord::creat(u, v, t, geometric, r, r.at(t), &(*self).mat_ptr);
+        rec.tangent = (*self).dpdu();
+        if let Some(n) = &(*self).normals {
+            let mut shading = unit_vector(n[0] * b0 + n[1] * b1 + n[2] * b2);
+            if dot(shading, geometric) < 0.0 {
+                shading = Vec3 { e: [0.0; 3] } - shading;
+            }
+            rec.normal = if rec.front_face {
+                shading
+            } else {
+                Vec3 { e: [0.0; 3] } - shading
+            };
+        }
+        Some(rec)
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut Aabb) -> bool {
+        let mut min = (*self).v[0];
+        let mut max = (*self).v[0];
+        for p in &(*self).v[1..] {
+            for c in 0..3 {
+                min.e[c] = fmin(min.e[c], p.e[c]);
+                max.e[c] = fmax(max.e[c], p.e[c]);
+            }
+        }
+        // pad so that axis-aligned triangles still get a box with volume
+        for c in 0..3 {
+            min.e[c] -= 0.0001;
+            max.e[c] += 0.0001;
+        }
+        *output_box = Aabb { min, max };
+        true
+    }
+
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        match (*self).hit(
+            Ray {
+                st: o,
+                dir: v,
+                tm: 0.0,
+            },
+            0.001,
+            INFINITY,
+            &mut NullSampler,
+        ) {
+            Some(rec) => {
+                let normal = unit_vector(cross(
+                    (*self).v[1] - (*self).v[0],
+                    (*self).v[2] - (*self).v[0],
+                ));
+                let distance_squared = rec.t.powi(2) * v.length_squared();
+                let cosine = (dot(v, normal) / v.length()).abs();
+                distance_squared / (cosine * (*self).area())
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, o: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (r1, r2) = sampler.get_2d();
+        let r1 = r1.sqrt();
+        let random_point =
+            (*self).v[0] * (1.0 - r1) + (*self).v[1] * (r1 * (1.0 - r2)) + (*self).v[2] * (r1 * r2);
+        random_point - o
+    }
+
+    fn power(&self) -> f64 {
+        diffuse_power((*self).area(), &(*self).mat_ptr)
+    }
+}
@@ -0,0 +1,20 @@
+use super::Integrator;
+use crate::basic::{ray::Ray, vec3::Color};
+use crate::hittable::Hittable;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use std::f64::INFINITY;
+
+// Shows the attenuation of the material at the first hit. Lights and misses
+// are black.
+pub struct AlbedoIntegrator {}
+
+impl Integrator for AlbedoIntegrator {
+    fn ray_color(&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        (*scene)
+            .world
+            .hit(r, 0.001, INFINITY, sampler)
+            .and_then(|rec| rec.mat_ptr.scatter(r, &rec, sampler))
+            .map_or(Color { e: [0.0; 3] }, |srec| srec.attenuation)
+    }
+}
@@ -0,0 +1,34 @@
+use super::Integrator;
+use crate::basic::{onb::Onb, ray::Ray, vec3::Color};
+use crate::hittable::Hittable;
+use crate::pdf::random_cosine_direction;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use std::f64::INFINITY;
+
+// Ambient occlusion: the cosine weighted fraction of the hemisphere above the
+// first hit that is open up to `distance`. Misses are white.
+pub struct AoIntegrator {
+    pub distance: f64,
+}
+
+impl Integrator for AoIntegrator {
+    fn ray_color(&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        let rec = match (*scene).world.hit(r, 0.001, INFINITY, sampler) {
+            Some(rec) => rec,
+            None => return Color { e: [1.0; 3] },
+        };
+        let mut uvw: Onb = Default::default();
+        uvw.build_from_w(rec.normal);
+        let probe = Ray {
+            st: rec.p,
+            dir: uvw.local_vec(random_cosine_direction(sampler)),
+            tm: r.get_time(),
+        };
+        // the direction is a unit vector, so t is the distance
+        match (*scene).world.hit(probe, 0.001, (*self).distance, sampler) {
+            Some(_) => Color { e: [0.0; 3] },
+            None => Color { e: [1.0; 3] },
+        }
+    }
+}
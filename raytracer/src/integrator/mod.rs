@@ -0,0 +1,116 @@
+pub mod albedo;
+pub mod ao;
+pub mod nee;
+pub mod normals;
+pub mod path;
+
+pub use albedo::AlbedoIntegrator;
+pub use ao::AoIntegrator;
+pub use nee::NeeIntegrator;
+pub use normals::NormalsIntegrator;
+pub use path::PathIntegrator;
+
+use crate::basic::{ray::Ray, vec3::Color};
+use crate::hittable::{HitRecord, Hittable};
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+
+// Estimates the light (or whatever quantity the integrator shows) arriving
+// along `r` from `scene`.
+pub trait Integrator: Send + Sync {
+    fn ray_color(&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color;
+}
+
+// Russian roulette on a path that carries `throughput` after `bounce`
+// bounces: once `roulette_depth` bounces are done it survives with a
+// probability that follows the throughput and is weighted up by the returned
+// factor, so the estimate stays unbiased.
+fn roulette(
+    bounce: isize,
+    roulette_depth: isize,
+    throughput: Color,
+    sampler: &mut dyn Sampler,
+) -> Option<f64> {
+    if bounce < roulette_depth {
+        return Some(1.0);
+    }
+    let p = throughput
+        .x()
+        .max(throughput.y())
+        .max(throughput.z())
+        .min(1.0);
+    if sampler.get_1d() < p {
+        Some(1.0 / p)
+    } else {
+        None
+    }
+}
+
+// Light from the scene's delta lights reflected at `rec` back along `r_in`,
+// for a material of the given `attenuation`. These lights can't be hit by
+// chance, so every one of them gets a shadow ray.
+fn delta_lighting(
+    scene: &Scene,
+    r_in: Ray,
+    rec: &HitRecord,
+    attenuation: Color,
+    sampler: &mut dyn Sampler,
+) -> Color {
+    let mut sum = Color { e: [0.0; 3] };
+    for light in &(*scene).delta_lights {
+        let li = match light.sample_li(rec.p) {
+            Some(li) => li,
+            None => continue,
+        };
+        let shadow = Ray {
+            st: rec.p,
+            dir: li.dir,
+            tm: r_in.get_time(),
+        };
+        let f = rec.mat_ptr.scattering(r_in, rec, shadow, attenuation);
+        if f.near_zero() {
+            continue;
+        }
+        // `dir` is a unit vector, so t is the distance
+        if (*scene)
+            .world
+            .hit(shadow, 0.001, li.distance - 0.001, sampler)
+            .is_none()
+        {
+            sum += f.mul(li.radiance);
+        }
+    }
+    sum
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IntegratorKind {
+    Path,
+    Nee,
+    Normals,
+    Albedo,
+    Ao,
+}
+
+pub fn creat(
+    kind: IntegratorKind,
+    max_depth: isize,
+    roulette_depth: isize,
+    ao_distance: f64,
+) -> Box<dyn Integrator> {
+    match kind {
+        IntegratorKind::Path => Box::new(PathIntegrator {
+            max_depth,
+            roulette_depth,
+        }),
+        IntegratorKind::Nee => Box::new(NeeIntegrator {
+            max_depth,
+            roulette_depth,
+        }),
+        IntegratorKind::Normals => Box::new(NormalsIntegrator {}),
+        IntegratorKind::Albedo => Box::new(AlbedoIntegrator {}),
+        IntegratorKind::Ao => Box::new(AoIntegrator {
+            distance: ao_distance,
+        }),
+    }
+}
@@ -0,0 +1,110 @@
+use super::{delta_lighting, roulette, Integrator};
+use crate::basic::{ray::Ray, vec3::Color};
+use crate::hittable::Hittable;
+use crate::pdf::Pdf;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use std::f64::INFINITY;
+
+// Path tracing with next event estimation: every diffuse vertex also samples a
+// point on the lights and traces a shadow ray towards it. Light hit by either
+// strategy is weighted with the power heuristic, so each strategy counts most
+// where it has the lower variance.
+pub struct NeeIntegrator {
+    pub max_depth: isize,
+    // bounces before Russian roulette may end a path
+    pub roulette_depth: isize,
+}
+
+fn power_heuristic(f: f64, g: f64) -> f64 {
+    let (f2, g2) = (f * f, g * g);
+    if f2 + g2 > 0.0 {
+        f2 / (f2 + g2)
+    } else {
+        0.0
+    }
+}
+
+impl Integrator for NeeIntegrator {
+    fn ray_color(&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        let world = &(*scene).world;
+        let lights = &(*scene).lights;
+        let has_lights = !lights.is_empty();
+        let mut radiance = Color { e: [0.0; 3] };
+        let mut throughput = Color { e: [1.0; 3] };
+        let mut r = r;
+        // pdf of the material sample that produced `r`; camera rays and
+        // specular bounces can't be matched by a light sample
+        let mut bsdf_pdf: Option<f64> = None;
+        for bounce in 0..(*self).max_depth {
+            let rec = world.hit(r, 0.001, INFINITY, sampler);
+            let emitted = match &rec {
+                Some(rec) => rec.mat_ptr.emitted(r, rec, rec.u, rec.v, rec.p),
+                None => scene.background(r.get_dir()),
+            };
+            if !emitted.near_zero() {
+                let weight = match bsdf_pdf {
+                    Some(pdf) if has_lights => {
+                        power_heuristic(pdf, lights.pdf_value(r.get_start(), r.get_dir()))
+                    }
+                    _ => 1.0,
+                };
+                radiance += throughput.mul(emitted) * weight;
+            }
+            let rec = match rec {
+                Some(rec) => rec,
+                None => break,
+            };
+            let srec = match rec.mat_ptr.scatter(r, &rec, sampler) {
+                Some(srec) => srec,
+                None => break,
+            };
+            if srec.is_specular {
+                throughput = throughput.mul(srec.attenuation);
+                r = srec.specular_ray;
+                bsdf_pdf = None;
+            } else {
+                let bsdf = srec.pdf_ptr.unwrap();
+                radiance +=
+                    throughput.mul(delta_lighting(scene, r, &rec, srec.attenuation, sampler));
+                if has_lights {
+                    let to_light = Ray {
+                        st: rec.p,
+                        dir: lights.random(rec.p, sampler),
+                        tm: r.get_time(),
+                    };
+                    let light_pdf = lights.pdf_value(rec.p, to_light.get_dir());
+                    let f = rec.mat_ptr.scattering(r, &rec, to_light, srec.attenuation);
+                    if light_pdf > 0.0 && !f.near_zero() {
+                        // whatever the shadow ray hits first is what's seen
+                        // in that direction, a blocker simply doesn't emit
+                        let le = match world.hit(to_light, 0.001, INFINITY, sampler) {
+                            Some(hit) => hit.mat_ptr.emitted(to_light, &hit, hit.u, hit.v, hit.p),
+                            None => scene.background(to_light.get_dir()),
+                        };
+                        let weight = power_heuristic(light_pdf, bsdf.value(to_light.get_dir()));
+                        radiance += throughput.mul(f.mul(le)) * (weight / light_pdf);
+                    }
+                }
+                let scattered = Ray {
+                    st: rec.p,
+                    dir: bsdf.generate(sampler),
+                    tm: r.get_time(),
+                };
+                let pdf = bsdf.value(scattered.get_dir());
+                if pdf <= 0.0 {
+                    break;
+                }
+                throughput = throughput
+                    .mul(rec.mat_ptr.scattering(r, &rec, scattered, srec.attenuation) / pdf);
+                r = scattered;
+                bsdf_pdf = Some(pdf);
+            }
+            match roulette(bounce, (*self).roulette_depth, throughput, sampler) {
+                Some(scale) => throughput *= scale,
+                None => break,
+            }
+        }
+        radiance
+    }
+}
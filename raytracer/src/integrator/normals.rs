@@ -0,0 +1,19 @@
+use super::Integrator;
+use crate::basic::{ray::Ray, vec3::Color};
+use crate::hittable::Hittable;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use std::f64::INFINITY;
+
+// Shows the normal at the first hit, facing the camera, mapped from [-1, 1]
+// to [0, 1]. Misses are black.
+pub struct NormalsIntegrator {}
+
+impl Integrator for NormalsIntegrator {
+    fn ray_color(&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        match (*scene).world.hit(r, 0.001, INFINITY, sampler) {
+            Some(rec) => (rec.normal + Color { e: [1.0; 3] }) * 0.5,
+            None => Color { e: [0.0; 3] },
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use super::{delta_lighting, roulette, Integrator};
+use crate::basic::{ray::Ray, vec3::Color};
+use crate::hittable::Hittable;
+use crate::pdf::{HittablePdf, MixturePdf, Pdf};
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use std::f64::INFINITY;
+
+// Path tracing, sampling a mixture of the lights and the material at every
+// diffuse bounce.
+pub struct PathIntegrator {
+    pub max_depth: isize,
+    // bounces before Russian roulette may end a path
+    pub roulette_depth: isize,
+}
+
+impl Integrator for PathIntegrator {
+    fn ray_color(&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        let lights = &(*scene).lights;
+        let mut radiance = Color { e: [0.0; 3] };
+        let mut throughput = Color { e: [1.0; 3] };
+        let mut r = r;
+        for bounce in 0..(*self).max_depth {
+            let rec = match (*scene).world.hit(r, 0.001, INFINITY, sampler) {
+                Some(rec) => rec,
+                None => {
+                    radiance += throughput.mul(scene.background(r.get_dir()));
+                    break;
+                }
+            };
+            radiance += throughput.mul(rec.mat_ptr.emitted(r, &rec, rec.u, rec.v, rec.p));
+            let srec = match rec.mat_ptr.scatter(r, &rec, sampler) {
+                Some(srec) => srec,
+                None => break,
+            };
+            let (scattered, weight) = if srec.is_specular {
+                (srec.specular_ray, srec.attenuation)
+            } else {
+                radiance +=
+                    throughput.mul(delta_lighting(scene, r, &rec, srec.attenuation, sampler));
+                let light_pdf = HittablePdf::creat(lights, rec.p);
+                let light_weight = if lights.is_empty() { 0.0 } else { 1.0 };
+                let p =
+                    MixturePdf::creat_weighted(light_pdf, light_weight, srec.pdf_ptr.unwrap(), 1.0);
+                let scattered = Ray {
+                    st: rec.p,
+                    dir: p.generate(sampler),
+                    tm: r.get_time(),
+                };
+                let pdf_val = p.value(scattered.get_dir());
+                // glossy lobes can send samples below the surface
+                if pdf_val <= 0.0 {
+                    break;
+                }
+                let weight = rec.mat_ptr.scattering(r, &rec, scattered, srec.attenuation) / pdf_val;
+                (scattered, weight)
+            };
+            throughput = throughput.mul(weight);
+            match roulette(bounce, (*self).roulette_depth, throughput, sampler) {
+                Some(scale) => throughput *= scale,
+                None => break,
+            }
+            r = scattered;
+        }
+        radiance
+    }
+}
@@ -0,0 +1,30 @@
+use super::{DeltaLight, LightSample};
+use crate::basic::vec3::{unit_vector, Color, Point3, Vec3};
+use std::f64::INFINITY;
+
+// Parallel light from infinitely far away, like a sun with no disc.
+// `direction` points towards the light, `irradiance` is what a surface facing
+// it receives.
+pub struct DirectionalLight {
+    direction: Vec3,
+    irradiance: Color,
+}
+
+impl DirectionalLight {
+    pub fn creat(direction: Vec3, irradiance: Color) -> DirectionalLight {
+        DirectionalLight {
+            direction: unit_vector(direction),
+            irradiance,
+        }
+    }
+}
+
+impl DeltaLight for DirectionalLight {
+    fn sample_li(&self, _p: Point3) -> Option<LightSample> {
+        Some(LightSample {
+            dir: (*self).direction,
+            distance: INFINITY,
+            radiance: (*self).irradiance,
+        })
+    }
+}
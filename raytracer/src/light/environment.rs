@@ -0,0 +1,253 @@
+use super::InfiniteLight;
+use crate::basic::{
+    clamp, degrees_to_radians,
+    distribution::Distribution2d,
+    ray::Ray,
+    vec3::{unit_vector, Color, Point3, Vec3},
+};
+use crate::bvh::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::output::tonemap::luminance;
+use crate::sampler::Sampler;
+use image::codecs::hdr::HdrDecoder;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+// Light looked up by direction in an equirectangular image whose top row is
+// straight up (+y) and whose center column looks down +x, like the uv
+// mapping of `Sphere`. Added to the lights it is importance sampled by
+// luminance.
+pub struct EnvironmentLight {
+    pixels: Vec<Color>,
+    width: usize,
+    height: usize,
+    distribution: Distribution2d,
+    scale: f64,
+    // about the y axis, in radians
+    rotation: f64,
+}
+
+fn srgb_decode(x: f64) -> f64 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Linear radiance of every pixel of `file`, top row first. Radiance HDR files
+// are read as floats, anything else goes through `image` as sRGB.
+fn load(file: &str) -> Result<(Vec<Color>, usize, usize), String> {
+    let is_hdr = matches!(Path::new(file).extension(), Some(e) if e.eq_ignore_ascii_case("hdr"));
+    if is_hdr {
+        let decoder = File::open(file)
+            .map_err(|e| e.to_string())
+            .and_then(|f| HdrDecoder::new(BufReader::new(f)).map_err(|e| e.to_string()))?;
+        let meta = decoder.metadata();
+        let data = decoder.read_image_hdr().map_err(|e| e.to_string())?;
+        let pixels = data
+            .iter()
+            .map(|p| Color {
+                e: [p.0[0] as f64, p.0[1] as f64, p.0[2] as f64],
+            })
+            .collect();
+        Ok((pixels, meta.width as usize, meta.height as usize))
+    } else {
+        let img = image::open(file).map_err(|e| e.to_string())?.to_rgb8();
+        let pixels = img
+            .pixels()
+            .map(|p| Color {
+                e: [
+                    srgb_decode(p.0[0] as f64 / 255.0),
+                    srgb_decode(p.0[1] as f64 / 255.0),
+                    srgb_decode(p.0[2] as f64 / 255.0),
+                ],
+            })
+            .collect();
+        Ok((pixels, img.width() as usize, img.height() as usize))
+    }
+}
+
+fn rotate_y(v: Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    Vec3 {
+        e: [cos * v.x() + sin * v.z(), v.y(), -sin * v.x() + cos * v.z()],
+    }
+}
+
+impl EnvironmentLight {
+    // `scale` multiplies the radiance of the image, `rotation` turns it about
+    // the y axis by that many degrees.
+    pub fn open(file: &str, scale: f64, rotation: f64) -> Result<EnvironmentLight, String> {
+        let (pixels, width, height) = load(file)?;
+        if width == 0 || height == 0 {
+            return Err("the image is empty".to_string());
+        }
+        Ok(EnvironmentLight::creat(
+            pixels, width, height, scale, rotation,
+        ))
+    }
+
+    // From linear radiance `pixels`, top row first.
+    pub fn creat(
+        pixels: Vec<Color>,
+        width: usize,
+        height: usize,
+        scale: f64,
+        rotation: f64,
+    ) -> EnvironmentLight {
+        // weighted by the solid angle each row covers
+        let func: Vec<f64> = (0..height)
+            .flat_map(|y| {
+                let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+                pixels[y * width..(y + 1) * width]
+                    .iter()
+                    .map(move |c| luminance(*c) * sin_theta)
+            })
+            .collect();
+        EnvironmentLight {
+            distribution: Distribution2d::creat(&func, width, height),
+            pixels,
+            width,
+            height,
+            scale,
+            rotation: degrees_to_radians(rotation),
+        }
+    }
+
+    // Image coordinates in [0, 1)^2 of world direction `dir`, from the top left.
+    fn image_coords(&self, dir: Vec3) -> (f64, f64) {
+        let d = unit_vector(rotate_y(dir, -(*self).rotation));
+        let theta = clamp(d.y(), -1.0, 1.0).acos();
+        let phi = (-d.z()).atan2(d.x()) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    fn direction(&self, x: f64, y: f64) -> Vec3 {
+        let theta = PI * y;
+        let phi = 2.0 * PI * x - PI;
+        let d = Vec3 {
+            e: [
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                -theta.sin() * phi.sin(),
+            ],
+        };
+        rotate_y(d, (*self).rotation)
+    }
+}
+
+impl InfiniteLight for EnvironmentLight {
+    fn radiance(&self, dir: Vec3) -> Color {
+        let (x, y) = (*self).image_coords(dir);
+        let i = ((x * (*self).width as f64) as usize).min((*self).width - 1);
+        let j = ((y * (*self).height as f64) as usize).min((*self).height - 1);
+        (*self).pixels[j * (*self).width + i] * (*self).scale
+    }
+}
+
+impl Hittable for EnvironmentLight {
+    fn hit(
+        &self,
+        _r: Ray,
+        _t_min: f64,
+        _t_max: f64,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        None
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64, _output_box: &mut Aabb) -> bool {
+        false
+    }
+
+    fn pdf_value(&self, _o: Point3, v: Vec3) -> f64 {
+        let (x, y) = (*self).image_coords(v);
+        let sin_theta = (PI * y).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        // from image area to solid angle
+        (*self).distribution.pdf(x, y) / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn random(&self, _o: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (u, v) = sampler.get_2d();
+        let ((x, y), _) = (*self).distribution.sample(u, v);
+        (*self).direction(x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+
+    // 16 x 8 pixels with a bright spot over a dim gradient
+    fn light() -> EnvironmentLight {
+        let (width, height) = (16, 8);
+        let pixels = (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                let v = if (x, y) == (5, 2) {
+                    50.0
+                } else {
+                    0.1 + 0.05 * x as f64
+                };
+                Color {
+                    e: [v, 0.5 * v, 0.2],
+                }
+            })
+            .collect();
+        EnvironmentLight::creat(pixels, width, height, 2.0, 30.0)
+    }
+
+    // Sum of f(dir) d(omega) over a fine grid of image coordinates.
+    fn integrate(env: &EnvironmentLight, f: impl Fn(Vec3) -> f64) -> f64 {
+        let n = 512;
+        let mut sum = 0.0;
+        for j in 0..n {
+            for i in 0..2 * n {
+                let (x, y) = (
+                    (i as f64 + 0.5) / (2 * n) as f64,
+                    (j as f64 + 0.5) / n as f64,
+                );
+                let d_omega = 2.0 * PI * PI * (PI * y).sin() / (2 * n * n) as f64;
+                sum += f(env.direction(x, y)) * d_omega;
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn pdf_integrates_to_one_over_the_sphere() {
+        let env = light();
+        let o = Point3 { e: [0.0; 3] };
+        let total = integrate(&env, |d| env.pdf_value(o, d));
+        assert!((total - 1.0).abs() < 1e-3, "{}", total);
+    }
+
+    #[test]
+    fn sampled_directions_follow_the_pdf() {
+        let env = light();
+        let o = Point3 { e: [0.0; 3] };
+        let expected = integrate(&env, |d| luminance(env.radiance(d)));
+        let mut sampler = IndependentSampler::creat(1);
+        let n = 100000;
+        let mut sum = 0.0;
+        for i in 0..n {
+            sampler.start_pixel_sample(0, 0, i);
+            let d = env.random(o, &mut sampler);
+            sum += luminance(env.radiance(d)) / env.pdf_value(o, d);
+        }
+        let estimate = sum / n as f64;
+        assert!(
+            (estimate / expected - 1.0).abs() < 0.01,
+            "{} {}",
+            estimate,
+            expected
+        );
+    }
+}
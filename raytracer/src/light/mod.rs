@@ -0,0 +1,63 @@
+pub mod directional;
+pub mod environment;
+pub mod point;
+pub mod selection;
+pub mod sky;
+pub mod spot;
+
+pub use directional::DirectionalLight;
+pub use environment::EnvironmentLight;
+pub use point::PointLight;
+pub use selection::{LightList, LightSelection};
+pub use sky::Sky;
+pub use spot::SpotLight;
+
+use crate::basic::{
+    ray::Ray,
+    vec3::{Color, Point3, Vec3},
+};
+use crate::bvh::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::sampler::Sampler;
+use std::sync::Arc;
+
+// Light arriving from infinitely far away, seen by the rays that escape the
+// scene. As a `Hittable` it is never hit, but it can be added to the lights
+// to be sampled by direction.
+pub trait InfiniteLight: Hittable {
+    fn radiance(&self, dir: Vec3) -> Color;
+}
+
+impl Hittable for Arc<dyn InfiniteLight> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        (**self).hit(r, t_min, t_max, sampler)
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut Aabb) -> bool {
+        (**self).bounding_box(t0, t1, output_box)
+    }
+
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        (**self).pdf_value(o, v)
+    }
+
+    fn random(&self, o: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        (**self).random(o, sampler)
+    }
+}
+
+// Light arriving at a point from a delta light.
+pub struct LightSample {
+    // unit vector towards the light
+    pub dir: Vec3,
+    // to the light along `dir`, infinite for directional lights
+    pub distance: f64,
+    pub radiance: Color,
+}
+
+// A light at a single point or from a single direction. No ray can hit it by
+// chance, so integrators reach it with shadow rays only.
+pub trait DeltaLight: Send + Sync {
+    // None when `p` gets no light at all, e.g. outside a spot's cone.
+    fn sample_li(&self, p: Point3) -> Option<LightSample>;
+}
@@ -0,0 +1,23 @@
+use super::{DeltaLight, LightSample};
+use crate::basic::vec3::{Color, Point3};
+
+// Emits `intensity` in every direction from `position`.
+pub struct PointLight {
+    pub position: Point3,
+    pub intensity: Color,
+}
+
+impl DeltaLight for PointLight {
+    fn sample_li(&self, p: Point3) -> Option<LightSample> {
+        let to_light = (*self).position - p;
+        let distance = to_light.length();
+        if distance <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            dir: to_light / distance,
+            distance,
+            radiance: (*self).intensity / (distance * distance),
+        })
+    }
+}
@@ -0,0 +1,287 @@
+use crate::basic::{
+    distribution::Distribution1d,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+use crate::bvh::{
+    aabb::Aabb,
+    light_bvh::{LightBounds, LightBvh},
+};
+use crate::hittable::{hittable_list::HittableList, HitRecord, Hittable};
+use crate::sampler::Sampler;
+
+// bounded lights from which `Auto` switches from power to the light BVH
+const BVH_MIN_LIGHTS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightSelection {
+    // every light equally often
+    Uniform,
+    // in proportion to the emitted power
+    Power,
+    // by the estimated contribution at the shading point
+    Bvh,
+    // power, or the light BVH once there are many lights
+    Auto,
+}
+
+enum Selector {
+    Uniform,
+    Power(Distribution1d),
+    Bvh(LightBvh),
+}
+
+// The scene's lights, sampled as a whole by picking one of them and then a
+// direction towards it. Lights without bounds (environments, skies) have no
+// power to compare with, they share a fixed part of the samples.
+pub struct LightList {
+    lights: HittableList,
+    // indices of the lights with and without bounds
+    bounded: Vec<usize>,
+    infinite: Vec<usize>,
+    selector: Selector,
+}
+
+impl LightList {
+    pub fn creat(lights: HittableList, selection: LightSelection) -> LightList {
+        let mut bounded: Vec<usize> = Default::default();
+        let mut infinite: Vec<usize> = Default::default();
+        let mut bounds: Vec<LightBounds> = Default::default();
+        for (i, light) in lights.objects.iter().enumerate() {
+            let mut b: Aabb = Default::default();
+            if light.bounding_box(0.0, 1.0, &mut b) {
+                bounded.push(i);
+                bounds.push(LightBounds {
+                    bounds: b,
+                    power: light.power(),
+                });
+            } else {
+                infinite.push(i);
+            }
+        }
+        let selection = match selection {
+            LightSelection::Auto if bounded.len() >= BVH_MIN_LIGHTS => LightSelection::Bvh,
+            LightSelection::Auto => LightSelection::Power,
+            s => s,
+        };
+        let selector = match selection {
+            LightSelection::Power => Selector::Power(Distribution1d::creat(
+                &bounds.iter().map(|b| b.power).collect::<Vec<_>>(),
+            )),
+            LightSelection::Bvh => Selector::Bvh(LightBvh::creat(&bounds)),
+            _ => Selector::Uniform,
+        };
+        LightList {
+            lights,
+            bounded,
+            infinite,
+            selector,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        (*self).lights.objects.is_empty()
+    }
+
+    // Probability of picking one of the lights without bounds.
+    fn infinite_probability(&self) -> f64 {
+        let n = (*self).infinite.len() as f64;
+        if (*self).bounded.is_empty() {
+            1.0
+        } else {
+            n / (n + 1.0)
+        }
+    }
+}
+
+impl Hittable for LightList {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        (*self).lights.hit(r, t_min, t_max, sampler)
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut Aabb) -> bool {
+        (*self).lights.bounding_box(t0, t1, output_box)
+    }
+
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        let objects = &(*self).lights.objects;
+        let bounded = &(*self).bounded;
+        let p_bounded = match &(*self).selector {
+            Selector::Uniform => return (*self).lights.pdf_value(o, v),
+            Selector::Power(dist) => {
+                let mut sum = 0.0;
+                for (i, light) in bounded.iter().enumerate() {
+                    let prob = dist.piece_pdf(i) / bounded.len() as f64;
+                    if prob > 0.0 {
+                        sum += prob * objects[*light].pdf_value(o, v);
+                    }
+                }
+                sum
+            }
+            Selector::Bvh(bvh) => {
+                let r = Ray {
+                    st: o,
+                    dir: v,
+                    tm: 0.0,
+                };
+                bvh.pdf(r, &mut |i| objects[bounded[i]].pdf_value(o, v))
+            }
+        };
+        let p_infinite = (*self).infinite_probability();
+        let mut sum = (1.0 - p_infinite) * p_bounded;
+        for light in &(*self).infinite {
+            sum += p_infinite / (*self).infinite.len() as f64 * objects[*light].pdf_value(o, v);
+        }
+        sum
+    }
+
+    fn random(&self, o: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let objects = &(*self).lights.objects;
+        if let Selector::Uniform = (*self).selector {
+            return (*self).lights.random(o, sampler);
+        }
+        let u = sampler.get_1d();
+        let p_infinite = (*self).infinite_probability();
+        let id = if u < p_infinite {
+            let n = (*self).infinite.len();
+            (*self).infinite[((u / p_infinite * n as f64) as usize).min(n - 1)]
+        } else {
+            let u = ((u - p_infinite) / (1.0 - p_infinite)).min(1.0 - f64::EPSILON);
+            let n = (*self).bounded.len();
+            let i = match &(*self).selector {
+                Selector::Power(dist) => ((dist.sample(u).0 * n as f64) as usize).min(n - 1),
+                Selector::Bvh(bvh) => bvh.sample(o, u),
+                Selector::Uniform => unreachable!(),
+            };
+            (*self).bounded[i]
+        };
+        objects[id].random(o, sampler)
+    }
+
+    // infinite lights have no power to add up
+    fn power(&self) -> f64 {
+        let objects = &(*self).lights.objects;
+        (*self).bounded.iter().map(|i| objects[*i].power()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::vec3::{dot, unit_vector};
+    use crate::sampler::IndependentSampler;
+
+    // A light that sends every sample towards its center and whose pdf is
+    // one in that direction only, so that the pdf of the list in that
+    // direction is the probability of picking it.
+    struct Marker {
+        center: Option<Point3>,
+        power: f64,
+    }
+
+    impl Marker {
+        fn direction(&self, o: Point3) -> Vec3 {
+            match (*self).center {
+                Some(c) => unit_vector(c - o),
+                None => Vec3 {
+                    e: [0.0, 0.0, -1.0],
+                },
+            }
+        }
+    }
+
+    impl Hittable for Marker {
+        fn hit(
+            &self,
+            _r: Ray,
+            _t_min: f64,
+            _t_max: f64,
+            _s: &mut dyn Sampler,
+        ) -> Option<HitRecord> {
+            None
+        }
+        fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut Aabb) -> bool {
+            match (*self).center {
+                Some(c) => {
+                    let half = Vec3 { e: [0.1; 3] };
+                    *output_box = Aabb {
+                        min: c - half,
+                        max: c + half,
+                    };
+                    true
+                }
+                None => false,
+            }
+        }
+        fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+            if dot(unit_vector(v), (*self).direction(o)) > 1.0 - 1e-9 {
+                1.0
+            } else {
+                0.0
+            }
+        }
+        fn random(&self, o: Point3, _sampler: &mut dyn Sampler) -> Vec3 {
+            (*self).direction(o)
+        }
+        fn power(&self) -> f64 {
+            (*self).power
+        }
+    }
+
+    // Twenty lights of different power on a grid, and a sky.
+    fn markers() -> Vec<Marker> {
+        let mut markers: Vec<Marker> = (0..20)
+            .map(|i| Marker {
+                center: Some(Point3 {
+                    e: [(i % 5) as f64, 3.0, (i / 5) as f64 * 1.5],
+                }),
+                power: (1 + i % 7) as f64,
+            })
+            .collect();
+        markers.push(Marker {
+            center: None,
+            power: 0.0,
+        });
+        markers
+    }
+
+    fn check_frequencies(selection: LightSelection) {
+        let o = Point3 { e: [1.3, 0.5, 0.7] };
+        let mut lights: HittableList = Default::default();
+        for m in markers() {
+            lights.add(Box::new(m));
+        }
+        let list = LightList::creat(lights, selection);
+        let markers = markers();
+        let n = 200_000;
+        let mut hits = vec![0usize; markers.len()];
+        let mut sampler = IndependentSampler::creat(3);
+        for i in 0..n {
+            sampler.start_pixel_sample(0, 0, i);
+            let dir = list.random(o, &mut sampler);
+            let picked = markers
+                .iter()
+                .position(|m| m.pdf_value(o, dir) > 0.0)
+                .unwrap();
+            hits[picked] += 1;
+        }
+        let mut total = 0.0;
+        for (m, h) in markers.iter().zip(hits.iter()) {
+            let prob = list.pdf_value(o, m.direction(o));
+            let freq = *h as f64 / n as f64;
+            assert!((prob - freq).abs() < 3e-3, "{} {}", prob, freq);
+            total += prob;
+        }
+        assert!((total - 1.0).abs() < 1e-9, "{}", total);
+    }
+
+    #[test]
+    fn power_selection_pdf_matches_its_samples() {
+        check_frequencies(LightSelection::Power);
+    }
+
+    #[test]
+    fn bvh_selection_pdf_matches_its_samples() {
+        check_frequencies(LightSelection::Bvh);
+    }
+}
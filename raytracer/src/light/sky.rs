@@ -0,0 +1,232 @@
+use super::InfiniteLight;
+use crate::basic::{
+    clamp, degrees_to_radians,
+    onb::Onb,
+    ray::Ray,
+    vec3::{dot, unit_vector, Color, Point3, Vec3},
+};
+use crate::bvh::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::pdf::random_cosine_direction;
+use crate::sampler::Sampler;
+use std::f64::consts::PI;
+
+// the Preetham model is in kcd/m^2, this brings a white surface in full sun
+// to around 1
+const SCALE: f64 = 0.04;
+// luminance of the sun outside the atmosphere, in kcd/m^2
+const SUN_LUMINANCE: f64 = 1.6e6;
+// angular radius of the real sun
+const SUN_RADIUS: f64 = 0.2667;
+
+// Perez distribution coefficients A to E for Y, x and y as linear functions of
+// the turbidity, from Preetham et al., "A Practical Analytic Model for
+// Daylight".
+const PEREZ: [[(f64, f64); 5]; 3] = [
+    [
+        (0.1787, -1.4630),
+        (-0.3554, 0.4275),
+        (-0.0227, 5.3251),
+        (0.1206, -2.5771),
+        (-0.0670, 0.3703),
+    ],
+    [
+        (-0.0193, -0.2592),
+        (-0.0665, 0.0008),
+        (-0.0004, 0.2125),
+        (-0.0641, -0.8989),
+        (-0.0033, 0.0452),
+    ],
+    [
+        (-0.0167, -0.2608),
+        (-0.0950, 0.0092),
+        (-0.0079, 0.2102),
+        (-0.0441, -1.6537),
+        (-0.0109, 0.0529),
+    ],
+];
+
+fn perez(c: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    (1.0 + c[0] * (c[1] / cos_theta).exp())
+        * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos().powi(2))
+}
+
+fn polynomial(t: f64, theta: f64, rows: [[f64; 4]; 3]) -> f64 {
+    let f = |r: [f64; 4]| ((r[0] * theta + r[1]) * theta + r[2]) * theta + r[3];
+    t * t * f(rows[0]) + t * f(rows[1]) + f(rows[2])
+}
+
+fn yxy_to_rgb(big_y: f64, x: f64, y: f64) -> Color {
+    let big_x = x / y * big_y;
+    let big_z = (1.0 - x - y) / y * big_y;
+    Color {
+        e: [
+            3.2406 * big_x - 1.5372 * big_y - 0.4986 * big_z,
+            -0.9689 * big_x + 1.8758 * big_y + 0.0415 * big_z,
+            0.0557 * big_x - 0.2040 * big_y + 1.0570 * big_z,
+        ],
+    }
+}
+
+// Analytic daylight: the Preetham sky with a sun disc, black below the
+// horizon. Added to the lights it samples the sun and a cosine weighted upper
+// hemisphere.
+pub struct Sky {
+    sun: Vec3,
+    coefficients: [[f64; 5]; 3],
+    // Y, x and y at the zenith divided by the Perez function there
+    zenith: [f64; 3],
+    sun_radiance: Color,
+    cos_sun_radius: f64,
+    intensity: f64,
+}
+
+impl Sky {
+    // `sun` points towards the sun, `turbidity` is the haziness from 2 (very
+    // clear) to 10, `sun_radius` the angular radius of the disc in degrees.
+    // A bigger disc gives softer shadows with the same sunlight.
+    pub fn creat(sun: Vec3, turbidity: f64, sun_radius: f64, intensity: f64) -> Sky {
+        let sun = unit_vector(sun);
+        let t = turbidity;
+        let theta_s = clamp(sun.y(), -1.0, 1.0).acos();
+        let mut coefficients = [[0.0; 5]; 3];
+        for (c, p) in coefficients.iter_mut().zip(PEREZ.iter()) {
+            for (ci, (a, b)) in c.iter_mut().zip(p.iter()) {
+                *ci = a * t + b;
+            }
+        }
+
+        // the zenith values only hold for the sun above the horizon
+        let theta_z = theta_s.min(PI / 2.0);
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_z);
+        let zenith_y = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let zenith_x = polynomial(
+            t,
+            theta_z,
+            [
+                [0.00166, -0.00375, 0.00209, 0.0],
+                [-0.02903, 0.06377, -0.03202, 0.00394],
+                [0.11693, -0.21196, 0.06052, 0.25886],
+            ],
+        );
+        let zenith_yy = polynomial(
+            t,
+            theta_z,
+            [
+                [0.00275, -0.00610, 0.00317, 0.0],
+                [-0.04214, 0.08970, -0.04153, 0.00516],
+                [0.15346, -0.26756, 0.06670, 0.26688],
+            ],
+        );
+        let mut zenith = [zenith_y, zenith_x, zenith_yy];
+        for (z, c) in zenith.iter_mut().zip(coefficients.iter()) {
+            *z /= perez(c, 1.0, theta_z);
+        }
+
+        // sunlight through the atmosphere, Rayleigh and aerosol attenuation
+        // at 680, 550 and 440 nm
+        let sun_radiance = if sun.y() > 0.0 {
+            let theta_deg = theta_s.to_degrees();
+            let air_mass = 1.0 / (sun.y() + 0.15 * (93.885 - theta_deg).powf(-1.253));
+            let beta = 0.04608 * t - 0.04586;
+            let mut e = [0.0; 3];
+            for (v, lambda) in e.iter_mut().zip([0.68f64, 0.55, 0.44].iter()) {
+                let rayleigh = (-0.008735 * lambda.powf(-4.08) * air_mass).exp();
+                let aerosol = (-beta * lambda.powf(-1.3) * air_mass).exp();
+                *v = rayleigh * aerosol;
+            }
+            // the same sunlight spread over a disc of any size
+            let real = 1.0 - degrees_to_radians(SUN_RADIUS).cos();
+            let chosen = 1.0 - degrees_to_radians(sun_radius).cos();
+            Color { e } * (SUN_LUMINANCE * real / chosen)
+        } else {
+            Color { e: [0.0; 3] }
+        };
+
+        Sky {
+            sun,
+            coefficients,
+            zenith,
+            sun_radiance,
+            cos_sun_radius: degrees_to_radians(sun_radius).cos(),
+            intensity,
+        }
+    }
+
+    fn sun_probability(&self) -> f64 {
+        if (*self).sun.y() > 0.0 {
+            0.5
+        } else {
+            0.0
+        }
+    }
+}
+
+impl InfiniteLight for Sky {
+    fn radiance(&self, dir: Vec3) -> Color {
+        let d = unit_vector(dir);
+        if d.y() <= 0.0 {
+            return Color { e: [0.0; 3] };
+        }
+        let cos_gamma = dot(d, (*self).sun);
+        let gamma = clamp(cos_gamma, -1.0, 1.0).acos();
+        // keep the exponential in check right at the horizon
+        let cos_theta = d.y().max(0.01);
+        let mut v = [0.0; 3];
+        for (i, value) in v.iter_mut().enumerate() {
+            *value = (*self).zenith[i] * perez(&(*self).coefficients[i], cos_theta, gamma);
+        }
+        let mut color = yxy_to_rgb(v[0], v[1], v[2]);
+        for c in color.e.iter_mut() {
+            *c = c.max(0.0);
+        }
+        if cos_gamma >= (*self).cos_sun_radius {
+            color += (*self).sun_radiance;
+        }
+        color * (SCALE * (*self).intensity)
+    }
+}
+
+impl Hittable for Sky {
+    fn hit(
+        &self,
+        _r: Ray,
+        _t_min: f64,
+        _t_max: f64,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        None
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64, _output_box: &mut Aabb) -> bool {
+        false
+    }
+
+    fn pdf_value(&self, _o: Point3, v: Vec3) -> f64 {
+        let d = unit_vector(v);
+        let p_sun = (*self).sun_probability();
+        let sun = if dot(d, (*self).sun) >= (*self).cos_sun_radius {
+            1.0 / (2.0 * PI * (1.0 - (*self).cos_sun_radius))
+        } else {
+            0.0
+        };
+        let sky = d.y().max(0.0) / PI;
+        p_sun * sun + (1.0 - p_sun) * sky
+    }
+
+    fn random(&self, _o: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let mut uvw: Onb = Default::default();
+        if sampler.get_1d() < (*self).sun_probability() {
+            // uniform in the cone of the disc
+            uvw.build_from_w((*self).sun);
+            let (u, v) = sampler.get_2d();
+            let z = 1.0 - u * (1.0 - (*self).cos_sun_radius);
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let phi = 2.0 * PI * v;
+            uvw.local(r * phi.cos(), r * phi.sin(), z)
+        } else {
+            uvw.build_from_w(Vec3 { e: [0.0, 1.0, 0.0] });
+            uvw.local_vec(random_cosine_direction(sampler))
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use super::{DeltaLight, LightSample};
+use crate::basic::{
+    degrees_to_radians,
+    vec3::{dot, unit_vector, Color, Point3, Vec3},
+};
+
+// A point light that only shines into a cone around `direction`. Its
+// intensity fades out smoothly over the last `falloff` degrees of the cone.
+pub struct SpotLight {
+    position: Point3,
+    direction: Vec3,
+    intensity: Color,
+    cos_total: f64,
+    cos_falloff_start: f64,
+}
+
+impl SpotLight {
+    // `angle` is the half angle of the cone in degrees.
+    pub fn creat(
+        position: Point3,
+        direction: Vec3,
+        intensity: Color,
+        angle: f64,
+        falloff: f64,
+    ) -> SpotLight {
+        SpotLight {
+            position,
+            direction: unit_vector(direction),
+            intensity,
+            cos_total: degrees_to_radians(angle).cos(),
+            cos_falloff_start: degrees_to_radians((angle - falloff).max(0.0)).cos(),
+        }
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta <= (*self).cos_total {
+            0.0
+        } else if cos_theta >= (*self).cos_falloff_start {
+            1.0
+        } else {
+            // smoothstep
+            let t =
+                (cos_theta - (*self).cos_total) / ((*self).cos_falloff_start - (*self).cos_total);
+            t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+impl DeltaLight for SpotLight {
+    fn sample_li(&self, p: Point3) -> Option<LightSample> {
+        let to_light = (*self).position - p;
+        let distance = to_light.length();
+        if distance <= 0.0 {
+            return None;
+        }
+        let dir = to_light / distance;
+        let falloff = (*self).falloff(-dot(dir, (*self).direction));
+        if falloff <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            dir,
+            distance,
+            radiance: (*self).intensity * (falloff / (distance * distance)),
+        })
+    }
+}
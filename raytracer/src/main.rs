@@ -1,25 +1,32 @@
 use console::style;
-use image::{ImageBuffer, RgbImage};
 use rand::prelude::*;
 use std::f64::INFINITY;
+use std::process::exit;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::{fs::File, process::exit};
+use std::time::Instant;
 
 pub mod basic;
 pub mod bvh;
+pub mod cli;
+pub mod film;
 pub mod hittable;
+pub mod integrator;
+pub mod light;
 pub mod material;
+pub mod output;
 pub mod pdf;
+pub mod sampler;
+pub mod scene;
 pub mod texture;
 
 use basic::{
     camera::Camera,
-    clamp,
     ray::Ray,
-    vec3::{random_double, Color, Point3, Vec3},
+    vec3::{seed_random, Color, Point3, Vec3},
 };
-use bvh::BvhNode;
+use bvh::{aabb::Aabb, LinearBvh, SplitMethod};
+use film::Film;
 use hittable::{
     aarect::{XyRect, XzRect, YzRect},
     constant_medium::ConstantMedium,
@@ -32,77 +39,16 @@ use hittable::{
     translate::Translate,
     Hittable,
 };
+use integrator::Integrator;
+use light::{LightList, LightSelection};
 use material::{Dielectric, DiffuseLight, Lambertian, Metal};
-use pdf::{CosinePdf, Pdf};
+use output::tonemap::{ToneMap, ToneMapping};
+use sampler::{Sampler, SamplerKind};
+use scene::Scene;
 use texture::{ImageTexture, NoiseTexture};
 
-const QUALITY: u8 = 100;
-const MAXDEPTH: isize = 50;
-
-fn write_color(
-    pixel_color: Color,
-    samples_per_pixel: usize,
-    img: &mut RgbImage,
-    x: usize,
-    y: usize,
-    image_height: usize,
-) {
-    let pixel = (*img).get_pixel_mut(x as u32, (image_height - y - 1) as u32);
-    let scale = 1.0 / samples_per_pixel as f64;
-    let r_color = (pixel_color.x() * scale).sqrt();
-    let g_color = (pixel_color.y() * scale).sqrt();
-    let b_color = (pixel_color.z() * scale).sqrt();
-    let res: [u8; 3] = [
-        (256.0 * clamp(r_color, 0.0, 0.999)) as u8,
-        (256.0 * clamp(g_color, 0.0, 0.999)) as u8,
-        (256.0 * clamp(b_color, 0.0, 0.999)) as u8,
-    ];
-    *pixel = image::Rgb(res);
-}
 //Image
-#[allow(clippy::unnecessary_unwrap)]
-fn ray_color(r: Ray, background: Color, world: &impl Hittable, depth: isize) -> Color {
-    if depth <= 0 {
-        Color { e: [0.0; 3] }
-    } else {
-        let rec = (*world).hit(r, 0.001, INFINITY);
-        if rec.is_some() {
-            let rec = rec.unwrap();
-            let mut scattered: Ray = Default::default();
-            // let mut attenuation: Color = Default::default();
-            let emitted = rec.mat_ptr.emitted(r, rec.clone(), rec.u, rec.v, rec.p);
-            let mut pdf: f64 = 0.0;
-            let mut albedo: Color = Default::default();
-            if !rec
-                .mat_ptr
-                .scatter(r, rec.clone(), &mut albedo, &mut scattered, &mut pdf)
-            {
-                emitted
-            } else {
-                let p: CosinePdf = CosinePdf::creat(rec.normal);
-                scattered = Ray {
-                    st: rec.p,
-                    dir: p.generate(),
-                    tm: r.get_time(),
-                };
-                pdf = p.value(scattered.get_dir());
-
-                emitted
-                    + (albedo * rec.mat_ptr.scattering_pdf(r, rec, scattered)).mul(ray_color(
-                        scattered,
-                        background,
-                        world,
-                        depth - 1,
-                    )) / pdf
-            }
-        } else {
-            background
-        }
-    }
-}
-
-#[allow(dead_code)]
-fn final_scene() -> HittableList {
+fn final_scene(split: SplitMethod, selection: LightSelection) -> Scene {
     let mut rng = StdRng::seed_from_u64(19260817);
     let mut boxes1: HittableList = Default::default();
     let ground = Lambertian::creat(Color {
@@ -129,17 +75,20 @@ fn final_scene() -> HittableList {
     }
 
     let mut objects: HittableList = Default::default();
-    objects.add(Box::new(BvhNode::creat(boxes1.objects, 0.0, 1.0)));
+    objects.add(Box::new(LinearBvh::creat(boxes1.objects, 0.0, 1.0, split)));
 
     let light = DiffuseLight::creat_color(Color { e: [7.0; 3] });
-    objects.add(Box::new(XzRect {
+    let light_rect = XzRect {
         x0: 123.0,
         x1: 423.0,
         z0: 147.0,
         z1: 412.0,
         k: 554.0,
         mp: light,
-    }));
+    };
+    let mut lights: HittableList = Default::default();
+    lights.add(Box::new(light_rect.clone()));
+    objects.add(Box::new(FlipFace { ptr: light_rect }));
 
     let center1_ = Point3 {
         e: [400.0, 400.0, 200.0],
@@ -241,17 +190,40 @@ fn final_scene() -> HittableList {
     }
 
     objects.add(Box::new(Translate {
-        ptr: RotateY::creat(BvhNode::creat(boxes2.objects, 0.0, 1.0), 15.0),
+        ptr: RotateY::creat(LinearBvh::creat(boxes2.objects, 0.0, 1.0, split), 15.0),
         offset: Vec3 {
             e: [-100.0, 270.0, 395.0],
         },
     }));
 
-    objects
+    Scene {
+        world: objects,
+        lights: LightList::creat(lights, selection),
+        delta_lights: Default::default(),
+        cam: Camera::creat(
+            Point3 {
+                e: [478.0, 278.0, -600.0],
+            },
+            Point3 {
+                e: [278.0, 278.0, 0.0],
+            },
+            Vec3 { e: [0.0, 1.0, 0.0] },
+            40.0,
+            1.0,
+            0.0,
+            10.0,
+            0.0,
+            1.0,
+        ),
+        background: Color { e: [0.0; 3] },
+        environment: None,
+        image_width: 600,
+        image_height: 600,
+        samples_per_pixel: 500,
+    }
 }
 
-#[allow(dead_code)]
-fn cornell_box() -> HittableList {
+fn cornell_box(selection: LightSelection) -> Scene {
     let mut objects: HittableList = Default::default();
 
     let red = Lambertian::creat(Color {
@@ -284,16 +256,17 @@ fn cornell_box() -> HittableList {
         mp: red,
     }));
 
-    objects.add(Box::new(FlipFace {
-        ptr: XzRect {
-            x0: 213.0,
-            x1: 343.0,
-            z0: 227.0,
-            z1: 332.0,
-            k: 554.0,
-            mp: light,
-        },
-    }));
+    let light_rect = XzRect {
+        x0: 213.0,
+        x1: 343.0,
+        z0: 227.0,
+        z1: 332.0,
+        k: 554.0,
+        mp: light,
+    };
+    let mut lights: HittableList = Default::default();
+    lights.add(Box::new(light_rect.clone()));
+    objects.add(Box::new(FlipFace { ptr: light_rect }));
 
     objects.add(Box::new(XzRect {
         x0: 0.0,
@@ -348,83 +321,92 @@ fn cornell_box() -> HittableList {
     };
     objects.add(Box::new(box2));
 
-    objects
+    Scene {
+        world: objects,
+        lights: LightList::creat(lights, selection),
+        delta_lights: Default::default(),
+        cam: Camera::creat(
+            Point3 {
+                e: [278.0, 278.0, -800.0],
+            },
+            Point3 {
+                e: [278.0, 278.0, 0.0],
+            },
+            Vec3 { e: [0.0, 1.0, 0.0] },
+            40.0,
+            1.0,
+            0.0,
+            10.0,
+            0.0,
+            1.0,
+        ),
+        background: Color { e: [0.0; 3] },
+        environment: None,
+        image_width: 600,
+        image_height: 600,
+        samples_per_pixel: 500,
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn solve(
-    cam: &Camera,
-    world: &HittableList,
+    scene: &Scene,
     j: usize,
-    background: Color,
-    samples_per_pixel: usize,
-    image_width: usize,
-    image_height: usize,
-) -> (usize, Vec<Color>) {
-    let mut ret: Vec<Color> = Default::default();
-    for i in 0..image_width {
-        let mut pixel_color: Color = Color { e: [0.0; 3] };
-        for _ in 0..samples_per_pixel {
-            let u = (i as f64 + random_double()) / ((image_width - 1) as f64);
-            let v = (j as f64 + random_double()) / ((image_height - 1) as f64);
-            let r: Ray = (*cam).get_ray(u, v);
-            pixel_color += ray_color(r, background, world, MAXDEPTH);
+    first_sample: usize,
+    samples: usize,
+    integrator: &dyn Integrator,
+    active: &[bool],
+    sampler: &mut dyn Sampler,
+    film: &mut Film,
+) {
+    for i in 0..(*scene).image_width {
+        if !active[j * (*scene).image_width + i] {
+            continue;
+        }
+        for s in first_sample..first_sample + samples {
+            sampler.start_pixel_sample(i, j, s);
+            let (du, dv) = sampler.get_2d();
+            let u = (i as f64 + du) / (((*scene).image_width - 1) as f64);
+            let v = (j as f64 + dv) / (((*scene).image_height - 1) as f64);
+            let r: Ray = (*scene).cam.get_ray(u, v, sampler);
+            let color = integrator.ray_color(r, scene, sampler);
+            film.add_sample(i as f64 + du, j as f64 + dv, color);
         }
-        ret.push(pixel_color);
     }
-    (j, ret)
 }
 
-fn main() {
-    // Image
-    let aspect_ratio = 1.0;
-    let image_width: usize = 600;
-    let image_height: usize = (image_width as f64 / aspect_ratio) as usize;
-    let samples_per_pixel = 500;
-
-    //world
-    let background: Color = Color { e: [0.0; 3] };
-
-    //Camera
-    let lookfrom = Point3 {
-        e: [278.0, 278.0, -800.0],
-    };
-    let lookat = Point3 {
-        e: [278.0, 278.0, 0.0],
-    };
-    let vup: Vec3 = Vec3 { e: [0.0, 1.0, 0.0] };
-    let dist_to_focus = 10.0;
-    let vfov = 40.0;
-    let time0 = 0.0;
-    let time1 = 1.0;
-    let aperture = 0.0;
-    let cam = Camera::creat(
-        lookfrom,
-        lookat,
-        vup,
-        vfov,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
-        time0,
-        time1,
-    );
-    let path = "output/output.jpg";
-    let mut img: RgbImage = ImageBuffer::new(image_width as u32, image_height as u32);
-
-    //Render
-
+// Renders samples `first_sample..first_sample + samples` of every `active`
+// pixel and splats them onto `film`. `max_spp` is the most samples any pixel
+// will take, which the sample patterns are laid out for.
+#[allow(clippy::too_many_arguments)]
+fn render_pass(
+    scene: &Arc<Scene>,
+    first_sample: usize,
+    samples: usize,
+    max_spp: usize,
+    active: &Arc<Vec<bool>>,
+    integrator: &Arc<dyn Integrator>,
+    sampler_kind: SamplerKind,
+    threads: usize,
+    seed: u64,
+    film: &mut Film,
+) {
+    let image_width = scene.image_width;
+    let image_height = scene.image_height;
+    let filter = film.filter;
     let mut handles = vec![];
 
     #[allow(clippy::mutex_atomic)]
     let lines = Arc::new(Mutex::new(0));
 
-    for _ in 0..32 {
+    for _ in 0..threads {
         let counter = Arc::clone(&lines);
-        let cam_ = cam;
-        let world_ = cornell_box(); //world.clone();
-        let background_ = background;
-        let handle = thread::spawn(move || -> Vec<(usize, Vec<Color>)> {
-            let mut ret: Vec<(usize, Vec<Color>)> = Default::default();
+        let scene_ = Arc::clone(scene);
+        let active_ = Arc::clone(active);
+        let integrator_ = Arc::clone(integrator);
+        let handle = thread::spawn(move || -> Vec<(usize, Film)> {
+            let mut sampler = sampler::creat(sampler_kind, max_spp, seed);
+            let mut ret: Vec<(usize, Film)> = Default::default();
             loop {
                 let mut num = counter.lock().unwrap();
                 if (*num) == image_height {
@@ -434,39 +416,270 @@ fn main() {
                 let y = *num;
                 *num += 1;
                 std::mem::drop(num);
-                ret.push(solve(
-                    &cam_,
-                    &world_,
+                let mut tile = Film::creat_scanline(image_width, image_height, filter, y);
+                solve(
+                    &scene_,
                     y,
-                    background_,
-                    samples_per_pixel,
-                    image_width,
-                    image_height,
-                ));
+                    first_sample,
+                    samples,
+                    &*integrator_,
+                    &active_,
+                    &mut *sampler,
+                    &mut tile,
+                );
+                ret.push((y, tile));
             }
         });
         handles.push(handle);
     }
-
+    let mut tiles: Vec<(usize, Film)> = Default::default();
     for handle in handles {
-        let color_vec = handle.join().unwrap();
-        for obj in color_vec {
-            let y = obj.0;
-            for x in 0..image_width {
-                write_color(obj.1[x], samples_per_pixel, &mut img, x, y, image_height);
+        tiles.extend(handle.join().unwrap());
+    }
+    // neighbouring scanlines overlap, merge in a fixed order so that the
+    // result does not depend on the thread count
+    tiles.sort_by_key(|t| t.0);
+    for (_, tile) in &tiles {
+        film.merge(tile);
+    }
+}
+
+fn save(film: &Film, path: &str, opts: &cli::Options) {
+    if let Err(e) = output::write(
+        path,
+        &opts.format,
+        &opts.tone_mapping,
+        film.width,
+        film.height,
+        &film.image(),
+    ) {
+        eprintln!("{}: {}", path, style(e).red());
+        exit(1);
+    }
+}
+
+fn main() {
+    let opts = cli::parse();
+    let seed = match opts.seed {
+        Some(seed) => seed,
+        None => {
+            let seed = rand::thread_rng().gen::<u64>();
+            eprintln!("Using seed {}", seed);
+            seed
+        }
+    };
+    // scene construction (perlin tables, median bvh splits, ...) is seeded too
+    seed_random(seed);
+
+    let build_start = Instant::now();
+    let mut scene = match opts.scene.as_str() {
+        "cornell_box" => cornell_box(opts.light_selection),
+        "final_scene" => final_scene(opts.split, opts.light_selection),
+        file => match scene::load(file, opts.split, opts.light_selection) {
+            Ok(scene) => scene,
+            Err(e) => {
+                eprintln!("{}: {}", file, style(e).red());
+                exit(1);
+            }
+        },
+    };
+    eprintln!("Scene built in {:.2?}", build_start.elapsed());
+    let aspect_ratio = scene.image_width as f64 / scene.image_height as f64;
+    match (opts.width, opts.height) {
+        (Some(w), Some(h)) => {
+            scene.image_width = w;
+            scene.image_height = h;
+            scene
+                .cam
+                .set_aspect_ratio(aspect_ratio, w as f64 / h as f64);
+        }
+        (Some(w), None) => {
+            scene.image_width = w;
+            scene.image_height = ((w as f64 / aspect_ratio) as usize).max(1);
+        }
+        (None, Some(h)) => {
+            scene.image_width = ((h as f64 * aspect_ratio) as usize).max(1);
+            scene.image_height = h;
+        }
+        (None, None) => {}
+    }
+    if let Some(spp) = opts.samples_per_pixel {
+        scene.samples_per_pixel = spp;
+    }
+    let scene = Arc::new(scene);
+    let image_width = scene.image_width;
+    let image_height = scene.image_height;
+    let samples_per_pixel = scene.samples_per_pixel;
+    let mut film = Film::creat(image_width, image_height, opts.filter);
+    let ao_distance = opts.ao_distance.unwrap_or_else(|| {
+        // a tenth of the scene's extent
+        let mut bbox: Aabb = Default::default();
+        if scene.world.bounding_box(0.0, 1.0, &mut bbox) {
+            0.1 * (bbox.max - bbox.min).length()
+        } else {
+            INFINITY
+        }
+    });
+    let integrator: Arc<dyn Integrator> = Arc::from(integrator::creat(
+        opts.integrator,
+        opts.max_depth,
+        opts.roulette_depth,
+        ao_distance,
+    ));
+
+    //Render
+
+    // without adaptive sampling every pixel takes exactly `samples_per_pixel`,
+    // with it that is only the average and converged pixels drop out
+    let max_spp = match &opts.adaptive {
+        Some(adaptive) => adaptive.max_spp.unwrap_or(4 * samples_per_pixel),
+        None => samples_per_pixel,
+    };
+    let pass_spp = if opts.progressive.is_some() || opts.adaptive.is_some() {
+        opts.pass_spp
+    } else {
+        samples_per_pixel
+    };
+    let mut budget = image_width * image_height * samples_per_pixel;
+    let mut active = vec![true; image_width * image_height];
+    let mut active_pixels = active.len();
+
+    let render_start = Instant::now();
+    let mut done = 0;
+    let mut pass = 0;
+    while active_pixels > 0 && done < max_spp {
+        let pass_start = Instant::now();
+        let samples = pass_spp.min(max_spp - done).min(budget / active_pixels);
+        if samples == 0 {
+            break;
+        }
+        render_pass(
+            &scene,
+            done,
+            samples,
+            max_spp,
+            &Arc::new(active.clone()),
+            &integrator,
+            opts.sampler,
+            opts.threads,
+            seed,
+            &mut film,
+        );
+        done += samples;
+        budget -= samples * active_pixels;
+        pass += 1;
+        if let Some(adaptive) = &opts.adaptive {
+            for y in 0..image_height {
+                for x in 0..image_width {
+                    let pixel = &mut active[y * image_width + x];
+                    if *pixel && film.relative_error(x, y) < adaptive.threshold {
+                        *pixel = false;
+                        active_pixels -= 1;
+                    }
+                }
+            }
+        }
+        if let Some(progressive) = &opts.progressive {
+            save(
+                &film,
+                progressive.preview.as_ref().unwrap_or(&opts.output),
+                &opts,
+            );
+        }
+        if pass_spp < max_spp {
+            eprintln!(
+                "Pass {}: {}/{} spp, {} pixels active, in {:.2?}",
+                pass,
+                done,
+                max_spp,
+                active_pixels,
+                pass_start.elapsed()
+            );
+        }
+        // stop early rather than overshoot the budget with another pass
+        if let Some(limit) = opts.progressive.as_ref().and_then(|p| p.time_limit) {
+            if render_start.elapsed() + pass_start.elapsed() > limit {
+                break;
             }
         }
     }
+    let average =
+        film.sample_counts().iter().sum::<usize>() as f64 / (image_width * image_height) as f64;
+    eprintln!(
+        "Done ! Rendered {:.1} spp on average in {:.2?}",
+        average,
+        render_start.elapsed()
+    );
 
-    eprintln!("Done !");
     //output
-    let output_image = image::DynamicImage::ImageRgb8(img);
-    let mut output_file = File::create(path).unwrap();
-    match output_image.write_to(&mut output_file, image::ImageOutputFormat::Jpeg(QUALITY)) {
-        Ok(_) => {}
-        // Err(_) => panic!("Outputting image fails."),
-        Err(_) => println!("{}", style("Outputting image fails.").red()),
+    // in progressive mode without a preview file the last pass already wrote it
+    let written = match &opts.progressive {
+        Some(p) => p.preview.is_none(),
+        None => false,
+    };
+    if !written {
+        save(&film, &opts.output, &opts);
+    }
+    if let Some((path, format)) = opts.adaptive.as_ref().and_then(|a| a.heatmap.as_ref()) {
+        let tone = ToneMapping {
+            operator: ToneMap::Clamp,
+            exposure: 0.0,
+        };
+        let colors = output::heatmap(&film.sample_counts(), max_spp);
+        if let Err(e) = output::write(path, format, &tone, image_width, image_height, &colors) {
+            eprintln!("{}: {}", path, style(e).red());
+            exit(1);
+        }
     }
 
     exit(0);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use film::{Filter, FilterKind};
+    use integrator::IntegratorKind;
+
+    fn render(threads: usize, kind: SamplerKind) -> Vec<Color> {
+        let mut scene = cornell_box(LightSelection::Power);
+        scene.image_width = 24;
+        scene.image_height = 24;
+        let scene = Arc::new(scene);
+        // a wide filter, so that the scanlines of different threads overlap
+        let filter = Filter {
+            kind: FilterKind::Mitchell,
+            radius: 2.0,
+        };
+        let mut film = Film::creat(24, 24, filter);
+        let integrator: Arc<dyn Integrator> =
+            Arc::from(integrator::creat(IntegratorKind::Nee, 8, 3, INFINITY));
+        render_pass(
+            &scene,
+            0,
+            4,
+            4,
+            &Arc::new(vec![true; 24 * 24]),
+            &integrator,
+            kind,
+            threads,
+            7,
+            &mut film,
+        );
+        film.image()
+    }
+
+    // the images must match bit for bit, not just closely
+    #[allow(clippy::float_cmp)]
+    #[test]
+    fn render_does_not_depend_on_the_thread_count() {
+        for kind in [SamplerKind::Independent, SamplerKind::Sobol] {
+            let one = render(1, kind);
+            let many = render(4, kind);
+            assert!(one.iter().any(|c| c.e != [0.0; 3]));
+            for (a, b) in one.iter().zip(many.iter()) {
+                assert_eq!(a.e, b.e);
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use super::microfacet::{roughness_to_alpha, Ggx, GgxReflectionPdf};
+use super::{Material, ScatterRecord};
+use crate::basic::{
+    clamp,
+    ray::Ray,
+    vec3::{dot, reflect, unit_vector, Color, Vec3},
+};
+use crate::hittable::HitRecord;
+use crate::sampler::Sampler;
+
+// Complex index of refraction (eta, k) of common metals at 650, 550 and 450 nm.
+pub fn conductor_ior(name: &str) -> Option<(Color, Color)> {
+    let (eta, k) = match name {
+        "gold" => ([0.143, 0.374, 1.442], [3.983, 2.385, 1.603]),
+        "copper" => ([0.200, 0.924, 1.102], [3.912, 2.452, 2.142]),
+        "aluminium" | "aluminum" => ([1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+        "silver" => ([0.155, 0.117, 0.138], [4.828, 3.122, 2.147]),
+        _ => return None,
+    };
+    Some((Color { e: eta }, Color { e: k }))
+}
+
+// Unpolarized Fresnel reflectance of a conductor seen from air, per channel.
+// `t3` is written as in pbrt's FrConductor, which clippy mistakes for a typo.
+#[allow(clippy::suspicious_operation_groupings)]
+pub fn fresnel_conductor(cos_i: f64, eta: Color, k: Color) -> Color {
+    let cos2 = clamp(cos_i, 0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let mut e = [0.0; 3];
+    for (i, r) in e.iter_mut().enumerate() {
+        let (eta2, k2) = (eta.e[i] * eta.e[i], k.e[i] * k.e[i]);
+        let t0 = eta2 - k2 - sin2;
+        let a2b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+        let t1 = a2b2 + cos2;
+        let a = (0.5 * (a2b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos2.sqrt() * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        *r = 0.5 * (rs + rp);
+    }
+    Color { e }
+}
+
+// Rough metal: GGX microfacets reflecting with the Fresnel term of a complex
+// index of refraction. Unlike `Metal` it conserves energy and has a pdf, so
+// it is sampled together with the lights.
+#[derive(Clone, Copy, Debug)]
+pub struct Conductor {
+    pub eta: Color,
+    pub k: Color,
+    pub ggx: Ggx,
+}
+
+impl Conductor {
+    // `roughness` in [0, 1], `anisotropy` in [0, 1) stretches the highlight
+    // along one tangent.
+    pub fn creat(eta: Color, k: Color, roughness: f64, anisotropy: f64) -> Conductor {
+        let (alpha_x, alpha_y) = roughness_to_alpha(roughness, anisotropy);
+        Conductor {
+            eta,
+            k,
+            ggx: Ggx { alpha_x, alpha_y },
+        }
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let unit_direction = unit_vector(r_in.get_dir());
+        if (*self).ggx.is_smooth() {
+            let cos_i = -dot(unit_direction, rec.normal);
+            return Some(ScatterRecord {
+                specular_ray: Ray {
+                    st: rec.p,
+                    dir: reflect(unit_direction, rec.normal),
+                    tm: r_in.get_time(),
+                },
+                is_specular: true,
+                attenuation: fresnel_conductor(cos_i, (*self).eta, (*self).k),
+                pdf_ptr: None,
+            });
+        }
+        Some(ScatterRecord {
+            specular_ray: Default::default(),
+            is_specular: false,
+            // reflectance at normal incidence, the color of the metal
+            attenuation: fresnel_conductor(1.0, (*self).eta, (*self).k),
+            pdf_ptr: Some(Box::new(GgxReflectionPdf::creat(
+                rec.shading_frame(),
+                Vec3 { e: [0.0; 3] } - unit_direction,
+                (*self).ggx,
+            ))),
+        })
+    }
+
+    fn scattering(&self, r_in: Ray, rec: &HitRecord, scattered: Ray, _attenuation: Color) -> Color {
+        let uvw = rec.shading_frame();
+        let wo = uvw.to_local(Vec3 { e: [0.0; 3] } - unit_vector(r_in.get_dir()));
+        let wi = uvw.to_local(unit_vector(scattered.get_dir()));
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color { e: [0.0; 3] };
+        }
+        let h = unit_vector(wo + wi);
+        let f = fresnel_conductor(dot(wo, h), (*self).eta, (*self).k);
+        f * ((*self).ggx.d(h) * (*self).ggx.g(wo, wi) / (4.0 * wo.z()))
+    }
+
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        let uvw = rec.shading_frame();
+        let wo = uvw.to_local(Vec3 { e: [0.0; 3] } - unit_vector(r_in.get_dir()));
+        let wi = uvw.to_local(unit_vector(scattered.get_dir()));
+        (*self).ggx.reflection_pdf(wo, wi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::vec3::Point3;
+    use crate::hittable::sphere::sphere_tangent;
+    use crate::material::microfacet::{
+        assert_energy, integrate_sphere, sampled_flux, scattered_flux,
+    };
+    use crate::output::tonemap::luminance;
+
+    #[test]
+    fn fresnel_is_a_reflectance() {
+        let (eta, k) = conductor_ior("gold").unwrap();
+        let f0 = fresnel_conductor(1.0, eta, k);
+        for c in 0..3 {
+            let (n, k) = (eta.e[c], k.e[c]);
+            let expected = ((n - 1.0).powi(2) + k * k) / ((n + 1.0).powi(2) + k * k);
+            assert!((f0.e[c] - expected).abs() < 1e-9);
+        }
+        for i in 0..=100 {
+            let f = fresnel_conductor(i as f64 / 100.0, eta, k);
+            assert!(f.e.iter().all(|r| (0.0..=1.0).contains(r)));
+        }
+        let grazing = fresnel_conductor(0.0, eta, k);
+        assert!(grazing.e.iter().all(|r| (r - 1.0).abs() < 1e-6));
+    }
+
+    fn hit_on_sphere(normal: Vec3, mat: &dyn Material) -> HitRecord {
+        let r = Ray {
+            st: normal * 2.0,
+            dir: Vec3 { e: [0.0; 3] } - normal,
+            tm: 0.0,
+        };
+        let mut rec = HitRecord::creat(0.0, 0.0, 1.0, normal, r, normal, mat);
+        rec.tangent = sphere_tangent(normal);
+        rec
+    }
+
+    #[test]
+    fn rough_metal_keeps_at_most_the_incoming_energy() {
+        let (eta, k) = conductor_ior("silver").unwrap();
+        for (roughness, anisotropy) in [(0.3, 0.0), (0.6, 0.8)].iter() {
+            let metal = Conductor::creat(eta, k, *roughness, *anisotropy);
+            let rec = hit_on_sphere(Vec3 { e: [0.0, 1.0, 0.0] }, &metal);
+            for wo in [[0.0, 1.0, 0.0], [0.6, 0.8, 0.0], [0.0, 0.2, 0.98]].iter() {
+                let wo = unit_vector(Vec3 { e: *wo });
+                let r_in = Ray {
+                    st: Point3 { e: [0.0; 3] } + wo,
+                    dir: Vec3 { e: [0.0; 3] } - wo,
+                    tm: 0.0,
+                };
+                let albedo = integrate_sphere(|wi| scattered_flux(&metal, r_in, &rec, 1.0, wi));
+                assert_energy(albedo, |sampler| {
+                    sampled_flux(&metal, r_in, &rec, 1.0, sampler)
+                });
+                assert!(albedo > 0.5, "{}", albedo);
+            }
+        }
+    }
+
+    // The anisotropic highlight follows the sphere's tangent, so it can't jump
+    // where an arbitrary frame would switch axes.
+    #[test]
+    fn anisotropic_highlight_is_continuous_over_the_surface() {
+        let (eta, k) = conductor_ior("aluminium").unwrap();
+        let metal = Conductor::creat(eta, k, 0.4, 0.9);
+        let eval = |x: f64| {
+            let n = unit_vector(Vec3 {
+                e: [x, 0.3, (1.0 - x * x - 0.09).sqrt()],
+            });
+            let rec = hit_on_sphere(n, &metal);
+            let uvw = rec.shading_frame();
+            let r_in = Ray {
+                st: rec.p + uvw.local(0.5, 0.0, 0.8),
+                dir: Vec3 { e: [0.0; 3] } - uvw.local(0.5, 0.0, 0.8),
+                tm: 0.0,
+            };
+            let scattered = Ray {
+                st: rec.p,
+                dir: uvw.local(-0.3, 0.3, 0.9),
+                tm: 0.0,
+            };
+            luminance(metal.scattering(r_in, &rec, scattered, Default::default()))
+        };
+        let (a, b) = (eval(0.8999), eval(0.9001));
+        assert!((a - b).abs() < 1e-3 * a.max(b), "{} {}", a, b);
+        // and the tangent does matter
+        let rec = hit_on_sphere(Vec3 { e: [0.0, 0.0, 1.0] }, &metal);
+        assert!((rec.shading_frame().u().x() - 1.0).abs() < 1e-12);
+    }
+}
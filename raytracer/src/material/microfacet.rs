@@ -0,0 +1,493 @@
+use crate::basic::{
+    clamp,
+    onb::Onb,
+    vec3::{cross, dot, reflect, refract, unit_vector, Vec3},
+};
+use crate::pdf::Pdf;
+use crate::sampler::Sampler;
+#[cfg(test)]
+use crate::{
+    basic::{ray::Ray, vec3::Color},
+    hittable::HitRecord,
+    material::Material,
+    output::tonemap::luminance,
+    sampler::IndependentSampler,
+};
+use std::f64::consts::PI;
+
+// below this alpha the surface is treated as perfectly smooth
+pub const SMOOTH_ALPHA: f64 = 1e-3;
+
+// Fresnel reflectance of an interface between dielectrics, `eta` being the
+// index on the far side over the index on the side of `cos_i` > 0.
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let (cos_i, eta) = if cos_i < 0.0 {
+        (-cos_i, 1.0 / eta)
+    } else {
+        (cos_i, eta)
+    };
+    let cos_i = clamp(cos_i, 0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        // total internal reflection
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+// Refraction of `wo` (pointing away) through a surface of normal `n` on its
+// side, None on total internal reflection.
+pub fn refract_out(wo: Vec3, n: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = dot(wo, n);
+    if (1.0 - cos_i * cos_i) / (eta * eta) >= 1.0 {
+        None
+    } else {
+        Some(refract(Vec3 { e: [0.0; 3] } - wo, n, 1.0 / eta))
+    }
+}
+
+// Squared perceptual roughness in [0, 1], stretched along the first tangent
+// for `anisotropy` > 0.
+pub fn roughness_to_alpha(roughness: f64, anisotropy: f64) -> (f64, f64) {
+    let aspect = (1.0 - 0.9 * anisotropy).sqrt();
+    let alpha = roughness * roughness;
+    (alpha / aspect, alpha * aspect)
+}
+
+// GGX (Trowbridge-Reitz) distribution of microfacet normals. Directions are in
+// the local frame of the surface, the normal being z.
+#[derive(Clone, Copy, Debug)]
+pub struct Ggx {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl Ggx {
+    pub fn is_smooth(&self) -> bool {
+        (*self).alpha_x.max((*self).alpha_y) < SMOOTH_ALPHA
+    }
+
+    pub fn d(&self, h: Vec3) -> f64 {
+        if h.z() <= 0.0 {
+            return 0.0;
+        }
+        let (ax, ay) = ((*self).alpha_x, (*self).alpha_y);
+        let t = (h.x() / ax).powi(2) + (h.y() / ay).powi(2) + h.z() * h.z();
+        1.0 / (PI * ax * ay * t * t)
+    }
+
+    fn lambda(&self, w: Vec3) -> f64 {
+        if w.z() == 0.0 {
+            return 0.0;
+        }
+        let a2 = ((*self).alpha_x * w.x()).powi(2) + ((*self).alpha_y * w.y()).powi(2);
+        ((1.0 + a2 / (w.z() * w.z())).sqrt() - 1.0) / 2.0
+    }
+
+    // masking of `w`
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + (*self).lambda(w))
+    }
+
+    // height correlated masking and shadowing
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + (*self).lambda(wo) + (*self).lambda(wi))
+    }
+
+    // Density of the normals visible from `wo` (z > 0) at `h`.
+    pub fn visible_pdf(&self, wo: Vec3, h: Vec3) -> f64 {
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+        (*self).g1(wo) * dot(wo, h).max(0.0) * (*self).d(h) / wo.z()
+    }
+
+    // Samples a normal visible from `wo` (z > 0), after Heitz, "Sampling the
+    // GGX Distribution of Visible Normals".
+    pub fn sample_visible(&self, wo: Vec3, u: f64, v: f64) -> Vec3 {
+        let (ax, ay) = ((*self).alpha_x, (*self).alpha_y);
+        // to the hemisphere configuration
+        let vh = unit_vector(Vec3 {
+            e: [ax * wo.x(), ay * wo.y(), wo.z()],
+        });
+        let len2 = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if len2 > 0.0 {
+            Vec3 {
+                e: [-vh.y(), vh.x(), 0.0],
+            } / len2.sqrt()
+        } else {
+            Vec3 { e: [1.0, 0.0, 0.0] }
+        };
+        let t2 = cross(vh, t1);
+        // a disc projected on the visible half of the hemisphere
+        let r = u.sqrt();
+        let phi = 2.0 * PI * v;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        // back to the ellipsoid configuration
+        unit_vector(Vec3 {
+            e: [ax * nh.x(), ay * nh.y(), nh.z().max(1e-6)],
+        })
+    }
+
+    // Density of `wi` reflected about a visible normal sampled for `wo`.
+    pub fn reflection_pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        if wi.z() <= 0.0 {
+            return 0.0;
+        }
+        (*self).reflected_pdf(wo, wi)
+    }
+
+    // The same on both sides of the surface: rough normals can reflect below
+    // it, where a refracting surface doesn't lose the sample.
+    pub fn reflected_pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z() <= 0.0 || (wo + wi).length_squared() == 0.0 {
+            return 0.0;
+        }
+        let h = unit_vector(wo + wi);
+        if dot(wo, h) <= 0.0 {
+            return 0.0;
+        }
+        (*self).visible_pdf(wo, h) / (4.0 * dot(wo, h))
+    }
+
+    // The rough dielectric of Walter et al., "Microfacet Models for
+    // Refraction through Rough Surfaces", for `wo` above the surface and the
+    // relative index `eta` below it. Reflection and transmission are chosen
+    // by the Fresnel term of the sampled normal.
+
+    // BSDF times the cosine. Radiance is squeezed by 1 / eta^2 when it
+    // crosses into the denser side.
+    pub fn dielectric_f(&self, wo: Vec3, wi: Vec3, eta: f64) -> f64 {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return 0.0;
+        }
+        if wi.z() > 0.0 {
+            let h = unit_vector(wo + wi);
+            let f = fresnel_dielectric(dot(wo, h), eta);
+            return f * (*self).d(h) * (*self).g(wo, wi) / (4.0 * wo.z());
+        }
+        match transmission_half(wo, wi, eta) {
+            Some(h) => {
+                let denom = dot(wi, h) + dot(wo, h) / eta;
+                let t = 1.0 - fresnel_dielectric(dot(wo, h), eta);
+                t * (*self).d(h) * (*self).g(wo, wi) * (dot(wi, h) * dot(wo, h)).abs()
+                    / (denom * denom * wo.z() * eta * eta)
+            }
+            None => 0.0,
+        }
+    }
+
+    // Density of `sample_dielectric`. A reflection can land below the
+    // surface and a refraction above it, where `dielectric_f` takes them for
+    // the other lobe, so both lobes count on both sides.
+    pub fn dielectric_pdf(&self, wo: Vec3, wi: Vec3, eta: f64) -> f64 {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return 0.0;
+        }
+        let mut pdf = (*self).reflected_pdf(wo, wi);
+        if pdf > 0.0 {
+            pdf *= fresnel_dielectric(dot(wo, unit_vector(wo + wi)), eta);
+        }
+        if let Some(h) = transmission_half(wo, wi, eta) {
+            let denom = dot(wi, h) + dot(wo, h) / eta;
+            let t = 1.0 - fresnel_dielectric(dot(wo, h), eta);
+            pdf += (*self).visible_pdf(wo, h) * dot(wi, h).abs() / (denom * denom) * t;
+        }
+        pdf
+    }
+
+    pub fn sample_dielectric(&self, wo: Vec3, eta: f64, sampler: &mut dyn Sampler) -> Vec3 {
+        let (u, v) = sampler.get_2d();
+        let h = (*self).sample_visible(wo, u, v);
+        let r = fresnel_dielectric(dot(wo, h), eta);
+        if sampler.get_1d() < r {
+            reflect(Vec3 { e: [0.0; 3] } - wo, h)
+        } else {
+            // r is 1 on total internal reflection
+            refract_out(wo, h, eta).unwrap_or(Vec3 {
+                e: [0.0, 0.0, -1.0],
+            })
+        }
+    }
+}
+
+// Microfacet normal refracting `wo` into `wi` on the other side, None when it
+// would face away from either.
+fn transmission_half(wo: Vec3, wi: Vec3, eta: f64) -> Option<Vec3> {
+    let mut h = unit_vector(wo + wi * eta);
+    if h.z() < 0.0 {
+        h = Vec3 { e: [0.0; 3] } - h;
+    }
+    if dot(h, wo) <= 0.0 || dot(h, wi) >= 0.0 {
+        None
+    } else {
+        Some(h)
+    }
+}
+
+// Directions reflected about the visible GGX normals, in world space.
+pub struct GgxReflectionPdf {
+    uvw: Onb,
+    // outgoing direction in the local frame
+    wo: Vec3,
+    ggx: Ggx,
+}
+
+impl GgxReflectionPdf {
+    // `wo` points away from the surface whose shading frame is `uvw`.
+    pub fn creat(uvw: Onb, wo: Vec3, ggx: Ggx) -> GgxReflectionPdf {
+        GgxReflectionPdf {
+            uvw,
+            wo: uvw.to_local(unit_vector(wo)),
+            ggx,
+        }
+    }
+}
+
+impl Pdf for GgxReflectionPdf {
+    fn value(&self, dir: Vec3) -> f64 {
+        let wi = (*self).uvw.to_local(unit_vector(dir));
+        (*self).ggx.reflection_pdf((*self).wo, wi)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let (u, v) = sampler.get_2d();
+        let h = (*self).ggx.sample_visible((*self).wo, u, v);
+        // `reflect` takes the incoming direction
+        let wi = reflect(Vec3 { e: [0.0; 3] } - (*self).wo, h);
+        (*self).uvw.local_vec(wi)
+    }
+}
+
+// Directions reflected or refracted by a rough dielectric, in world space.
+pub struct GgxDielectricPdf {
+    uvw: Onb,
+    wo: Vec3,
+    ggx: Ggx,
+    eta: f64,
+}
+
+impl GgxDielectricPdf {
+    // `wo` points away from the surface of normal `n`, `eta` is the index
+    // behind the surface over the one in front.
+    pub fn creat(n: Vec3, wo: Vec3, ggx: Ggx, eta: f64) -> GgxDielectricPdf {
+        let mut uvw: Onb = Default::default();
+        uvw.build_from_w(n);
+        GgxDielectricPdf {
+            uvw,
+            wo: uvw.to_local(unit_vector(wo)),
+            ggx,
+            eta,
+        }
+    }
+}
+
+impl Pdf for GgxDielectricPdf {
+    fn value(&self, dir: Vec3) -> f64 {
+        let wi = (*self).uvw.to_local(unit_vector(dir));
+        (*self).ggx.dielectric_pdf((*self).wo, wi, (*self).eta)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let wi = (*self)
+            .ggx
+            .sample_dielectric((*self).wo, (*self).eta, sampler);
+        (*self).uvw.local_vec(wi)
+    }
+}
+
+// Integral of `f` over the unit sphere on a fine grid, for the energy tests
+// of the materials.
+#[cfg(test)]
+pub fn integrate_sphere(f: impl Fn(Vec3) -> f64) -> f64 {
+    let (n_theta, n_phi) = (400, 800);
+    let (d_theta, d_phi) = (PI / n_theta as f64, 2.0 * PI / n_phi as f64);
+    let mut sum = 0.0;
+    for i in 0..n_theta {
+        let theta = (i as f64 + 0.5) * d_theta;
+        let (sin, cos) = theta.sin_cos();
+        for j in 0..n_phi {
+            let phi = (j as f64 + 0.5) * d_phi;
+            let w = Vec3 {
+                e: [sin * phi.cos(), sin * phi.sin(), cos],
+            };
+            sum += f(w) * sin * d_theta * d_phi;
+        }
+    }
+    sum
+}
+
+// A hit on the z = 0 plane, from outside (above) or from inside.
+#[cfg(test)]
+pub fn plane_hit(wo: Vec3, mat: &dyn Material) -> (Ray, HitRecord) {
+    let r = Ray {
+        st: wo,
+        dir: Vec3 { e: [0.0; 3] } - wo,
+        tm: 0.0,
+    };
+    let normal = Vec3 { e: [0.0, 0.0, 1.0] };
+    let rec = HitRecord::creat(0.0, 0.0, 1.0, normal, r, Vec3 { e: [0.0; 3] }, mat);
+    (r, rec)
+}
+
+// Fraction of the incoming flux carried by `radiance` leaving along `wi`:
+// refracted radiance is squeezed by 1 / eta^2, which the flux doesn't see.
+#[cfg(test)]
+pub fn flux(radiance: Color, rec: &HitRecord, eta: f64, wi: Vec3) -> f64 {
+    let l = luminance(radiance);
+    if dot(wi, rec.normal) < 0.0 {
+        l * eta * eta
+    } else {
+        l
+    }
+}
+
+// `flux` of what `mat` scatters along `wi`, to integrate over the sphere.
+#[cfg(test)]
+pub fn scattered_flux(mat: &dyn Material, r_in: Ray, rec: &HitRecord, eta: f64, wi: Vec3) -> f64 {
+    let scattered = Ray {
+        st: rec.p,
+        dir: wi,
+        tm: 0.0,
+    };
+    flux(
+        mat.scattering(r_in, rec, scattered, Default::default()),
+        rec,
+        eta,
+        wi,
+    )
+}
+
+// One sample of the energy `mat` scatters: f / pdf for a direction of its
+// pdf, or the attenuation of a specular bounce.
+#[cfg(test)]
+pub fn sampled_flux(
+    mat: &dyn Material,
+    r_in: Ray,
+    rec: &HitRecord,
+    eta: f64,
+    sampler: &mut dyn Sampler,
+) -> f64 {
+    let srec = match mat.scatter(r_in, rec, sampler) {
+        Some(srec) => srec,
+        None => return 0.0,
+    };
+    if srec.is_specular {
+        let wi = srec.specular_ray.get_dir();
+        return flux(srec.attenuation, rec, eta, wi);
+    }
+    let pdf = srec.pdf_ptr.unwrap();
+    let wi = pdf.generate(sampler);
+    let p = pdf.value(wi);
+    if p > 0.0 {
+        scattered_flux(mat, r_in, rec, eta, wi) / p
+    } else {
+        0.0
+    }
+}
+
+// No more than the incoming `energy` leaves, and the samples of `estimate`
+// agree with it on average.
+#[cfg(test)]
+pub fn assert_energy(energy: f64, mut estimate: impl FnMut(&mut dyn Sampler) -> f64) {
+    assert!(energy <= 1.0, "{}", energy);
+    let mut sampler = IndependentSampler::creat(4);
+    let n = 20000;
+    let mut sum = 0.0;
+    for i in 0..n {
+        sampler.start_pixel_sample(0, 0, i);
+        sum += estimate(&mut sampler);
+    }
+    let mean = sum / n as f64;
+    assert!((mean - energy).abs() < 0.02, "{} {}", mean, energy);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ggxs() -> Vec<Ggx> {
+        [(0.3, 0.0), (0.5, 0.0), (0.6, 0.8)]
+            .iter()
+            .map(|(r, a)| {
+                let (alpha_x, alpha_y) = roughness_to_alpha(*r, *a);
+                Ggx { alpha_x, alpha_y }
+            })
+            .collect()
+    }
+
+    fn direction(theta: f64, phi: f64) -> Vec3 {
+        Vec3 {
+            e: [
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            ],
+        }
+    }
+
+    #[test]
+    fn projected_normals_cover_the_surface_once() {
+        for ggx in ggxs() {
+            let area = integrate_sphere(|h| ggx.d(h) * h.z());
+            assert!((area - 1.0).abs() < 1e-2, "{:?} {}", ggx, area);
+        }
+    }
+
+    #[test]
+    fn visible_normals_are_a_distribution() {
+        for ggx in ggxs() {
+            for theta in [0.1, 0.8, 1.4].iter() {
+                let wo = direction(*theta, 0.7);
+                let total = integrate_sphere(|h| ggx.visible_pdf(wo, h));
+                assert!((total - 1.0).abs() < 1e-2, "{:?} {}", ggx, total);
+            }
+        }
+    }
+
+    // With a Fresnel term of one the reflected energy is what masking and
+    // shadowing leave, never more than came in.
+    #[test]
+    fn reflection_keeps_at_most_the_incoming_energy() {
+        for ggx in ggxs() {
+            for theta in [0.1, 0.8, 1.4].iter() {
+                let wo = direction(*theta, 0.3);
+                let albedo = integrate_sphere(|wi| {
+                    if wi.z() <= 0.0 {
+                        return 0.0;
+                    }
+                    ggx.d(unit_vector(wo + wi)) * ggx.g(wo, wi) / (4.0 * wo.z())
+                });
+                // f / pdf of a visible normal sample is the masking ratio
+                assert_energy(albedo, |sampler| {
+                    let (u, v) = sampler.get_2d();
+                    let wi = reflect(Vec3 { e: [0.0; 3] } - wo, ggx.sample_visible(wo, u, v));
+                    if wi.z() > 0.0 {
+                        ggx.g(wo, wi) / ggx.g1(wo)
+                    } else {
+                        0.0
+                    }
+                });
+                assert!(albedo > 0.5, "{:?} {}", ggx, albedo);
+            }
+        }
+    }
+
+    #[test]
+    fn dielectric_fresnel_limits() {
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert!((fresnel_dielectric(1e-9, 1.5) - 1.0).abs() < 1e-6);
+        // from inside, past the critical angle
+        assert!((fresnel_dielectric(-0.5, 1.5) - 1.0).abs() < 1e-12);
+        for i in 0..=100 {
+            let f = fresnel_dielectric(i as f64 / 100.0, 1.33);
+            assert!((0.0..=1.0).contains(&f));
+        }
+    }
+}
@@ -1,37 +1,89 @@
+pub mod conductor;
+pub mod microfacet;
+pub mod principled;
+pub mod rough_dielectric;
+
+pub use conductor::Conductor;
+pub use principled::Principled;
+pub use rough_dielectric::RoughDielectric;
+
 use crate::hittable::HitRecord;
 use crate::texture::*;
 
 use crate::basic::{
     fmax, fmin,
-    onb::Onb,
     ray::Ray,
-    vec3::{
-        dot, random_double, random_in_unit_sphere, reflect, refract, unit_vector, Color, Point3,
-        Vec3,
-    },
+    vec3::{dot, random_in_unit_sphere, reflect, refract, unit_vector, Color, Point3, Vec3},
 };
-use crate::pdf::random_cosine_direction;
+use crate::pdf::{CosinePdf, Pdf, SpherePdf};
+use crate::sampler::Sampler;
 use std::f64::consts::PI;
+use std::sync::Arc;
+
+pub struct ScatterRecord {
+    pub specular_ray: Ray,
+    pub is_specular: bool,
+    pub attenuation: Color,
+    pub pdf_ptr: Option<Box<dyn Pdf>>,
+}
 
 pub trait Material: Send + Sync {
     fn scatter(
         &self,
         _r_in: Ray,
-        _rec: HitRecord,
-        _albedo: &mut Color,
-        _scattered: &mut Ray,
-        _pdf: &mut f64,
-    ) -> bool {
-        false
+        _rec: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        None
     }
 
-    fn emitted(&self, _r_in: Ray, _rec: HitRecord, _u: f64, _v: f64, _p: Point3) -> Color {
+    fn emitted(&self, _r_in: Ray, _rec: &HitRecord, _u: f64, _v: f64, _p: Point3) -> Color {
         Color { e: [0.0; 3] }
     }
 
-    fn scattering_pdf(&self, _r_in: Ray, _rec: HitRecord, _scattered: Ray) -> f64 {
+    fn scattering_pdf(&self, _r_in: Ray, _rec: &HitRecord, _scattered: Ray) -> f64 {
         0.0
     }
+
+    // BSDF times the cosine towards `scattered`, for non-specular scattering.
+    // Diffuse materials are a color, the `attenuation` from `scatter`, spread
+    // like their `scattering_pdf`.
+    fn scattering(&self, r_in: Ray, rec: &HitRecord, scattered: Ray, attenuation: Color) -> Color {
+        attenuation * (*self).scattering_pdf(r_in, rec, scattered)
+    }
+
+    // Average radiance leaving the front face, to estimate the power of a
+    // light made of this material.
+    fn emission(&self) -> Color {
+        Color { e: [0.0; 3] }
+    }
+}
+
+impl Material for Arc<dyn Material> {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        (**self).scatter(r_in, rec, sampler)
+    }
+
+    fn emitted(&self, r_in: Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        (**self).emitted(r_in, rec, u, v, p)
+    }
+
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        (**self).scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn scattering(&self, r_in: Ray, rec: &HitRecord, scattered: Ray, attenuation: Color) -> Color {
+        (**self).scattering(r_in, rec, scattered, attenuation)
+    }
+
+    fn emission(&self) -> Color {
+        (**self).emission()
+    }
 }
 
 #[derive(Default, Clone)]
@@ -56,25 +108,18 @@ impl Lambertian<SolidColor> {
 impl<T: Texture> Material for Lambertian<T> {
     fn scatter(
         &self,
-        r_in: Ray,
-        rec: HitRecord,
-        alb: &mut Color,
-        scattered: &mut Ray,
-        pdf: &mut f64,
-    ) -> bool {
-        let mut uvw: Onb = Default::default();
-        uvw.build_from_w(rec.normal);
-        let dir = uvw.local_vec(random_cosine_direction());
-        *scattered = Ray {
-            st: rec.p,
-            dir: unit_vector(dir),
-            tm: r_in.get_time(),
-        };
-        *alb = (*self).albedo.value(rec.u, rec.v, rec.p);
-        *pdf = dot(uvw.w(), scattered.get_dir()) / PI;
-        true
+        _r_in: Ray,
+        rec: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            specular_ray: Default::default(),
+            is_specular: false,
+            attenuation: (*self).albedo.value(rec.u, rec.v, rec.p),
+            pdf_ptr: Some(Box::new(CosinePdf::creat(rec.normal))),
+        })
     }
-    fn scattering_pdf(&self, _r_in: Ray, rec: HitRecord, scattered: Ray) -> f64 {
+    fn scattering_pdf(&self, _r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
         let cosine = dot(rec.normal, unit_vector(scattered.get_dir()));
         fmax(0.0, cosine / PI)
     }
@@ -84,19 +129,25 @@ impl Material for Metal {
     fn scatter(
         &self,
         r_in: Ray,
-        rec: HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-        _pdf: &mut f64,
-    ) -> bool {
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
         let reflected = reflect(unit_vector(r_in.get_dir()), rec.normal);
-        *scattered = Ray {
+        let scattered = Ray {
             st: rec.p,
-            dir: reflected + random_in_unit_sphere() * (*self).fuzz,
+            dir: reflected + random_in_unit_sphere(sampler) * (*self).fuzz,
             tm: r_in.get_time(),
         };
-        *attenuation = (*self).albedo;
-        dot(scattered.get_dir(), rec.normal) > 0.0
+        if dot(scattered.get_dir(), rec.normal) > 0.0 {
+            Some(ScatterRecord {
+                specular_ray: scattered,
+                is_specular: true,
+                attenuation: (*self).albedo,
+                pdf_ptr: None,
+            })
+        } else {
+            None
+        }
     }
 }
 
@@ -109,12 +160,9 @@ impl Material for Dielectric {
     fn scatter(
         &self,
         r_in: Ray,
-        rec: HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-        _pdf: &mut f64,
-    ) -> bool {
-        *attenuation = Color { e: [1.0; 3] };
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
         let refraction_ratio = if rec.front_face {
             1.0 / ((*self).ir)
         } else {
@@ -127,19 +175,23 @@ impl Material for Dielectric {
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
         let direction = if cannot_refract
-            || Dielectric::reflectance(cos_theta, refraction_ratio) > random_double()
+            || Dielectric::reflectance(cos_theta, refraction_ratio) > sampler.get_1d()
         {
             reflect(unit_direction, rec.normal)
         } else {
             refract(unit_direction, rec.normal, refraction_ratio)
         };
 
-        *scattered = Ray {
-            st: rec.p,
-            dir: direction,
-            tm: r_in.get_time(),
-        };
-        true
+        Some(ScatterRecord {
+            specular_ray: Ray {
+                st: rec.p,
+                dir: direction,
+                tm: r_in.get_time(),
+            },
+            is_specular: true,
+            attenuation: Color { e: [1.0; 3] },
+            pdf_ptr: None,
+        })
     }
 }
 
@@ -181,24 +233,31 @@ impl<T: Texture> DiffuseLight<T> {
 }
 
 impl<T: Texture> Material for DiffuseLight<T> {
-    fn scatter(
-        &self,
-        _r_in: Ray,
-        _rec: HitRecord,
-        _attenuation: &mut Color,
-        _scattered: &mut Ray,
-        _pdf: &mut f64,
-    ) -> bool {
-        false
-    }
-
-    fn emitted(&self, _r_in: Ray, rec: HitRecord, u: f64, v: f64, p: Point3) -> Color {
+    fn emitted(&self, _r_in: Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
         if rec.front_face {
             (*self).emit.value(u, v, p)
         } else {
             Color { e: [0.0; 3] }
         }
     }
+
+    fn emission(&self) -> Color {
+        average_value(&(*self).emit)
+    }
+}
+
+// Textures are only known by their values, this averages a few of them.
+pub fn average_value(tex: &dyn Texture) -> Color {
+    let n = 4;
+    let mut sum = Color { e: [0.0; 3] };
+    for i in 0..n {
+        for j in 0..n {
+            let u = (i as f64 + 0.5) / n as f64;
+            let v = (j as f64 + 0.5) / n as f64;
+            sum += tex.value(u, v, Point3 { e: [0.0; 3] });
+        }
+    }
+    sum / (n * n) as f64
 }
 
 pub struct Isotropic<T: Texture> {
@@ -216,18 +275,20 @@ impl<T: Texture> Isotropic<T> {
 impl<T: Texture> Material for Isotropic<T> {
     fn scatter(
         &self,
-        r_in: Ray,
-        rec: HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-        _pdf: &mut f64,
-    ) -> bool {
-        *scattered = Ray {
-            st: rec.p,
-            dir: random_in_unit_sphere(),
-            tm: r_in.get_time(),
-        };
-        *attenuation = (*self).albedo.value(rec.u, rec.v, rec.p);
-        true
+        _r_in: Ray,
+        rec: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            specular_ray: Default::default(),
+            is_specular: false,
+            attenuation: (*self).albedo.value(rec.u, rec.v, rec.p),
+            pdf_ptr: Some(Box::new(SpherePdf)),
+        })
+    }
+
+    // the phase function, scattering the same way in every direction
+    fn scattering_pdf(&self, _r_in: Ray, _rec: &HitRecord, _scattered: Ray) -> f64 {
+        1.0 / (4.0 * PI)
     }
 }
@@ -0,0 +1,505 @@
+use super::microfacet::{fresnel_dielectric, refract_out, roughness_to_alpha, Ggx};
+use super::{average_value, Material, ScatterRecord};
+use crate::basic::{
+    clamp,
+    onb::Onb,
+    ray::Ray,
+    vec3::{dot, reflect, unit_vector, Color, Point3, Vec3},
+};
+use crate::hittable::HitRecord;
+use crate::output::tonemap::luminance;
+use crate::pdf::{random_cosine_direction, Pdf};
+use crate::sampler::Sampler;
+use crate::texture::{SolidColor, Texture};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+const CLEARCOAT_ROUGHNESS: f64 = 0.15;
+
+fn solid(v: f64) -> Arc<dyn Texture> {
+    Arc::new(SolidColor {
+        color_value: Color { e: [v; 3] },
+    })
+}
+
+fn schlick(f0: Color, cosine: f64) -> Color {
+    let m = (1.0 - clamp(cosine, 0.0, 1.0)).powi(5);
+    f0 + (Color { e: [1.0; 3] } - f0) * m
+}
+
+// One material for most surfaces, after Burley's "Physically Based Shading at
+// Disney": a diffuse base with sheen, a GGX specular layer going from
+// dielectric to metal, a clear coat and rough glass transmission. Every
+// parameter is a texture; the scalar ones read the mean of its channels,
+// clamped to [0, 1].
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    // reflectance of the non-metal part, 0.5 being an index of 1.5
+    pub specular: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub sheen: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    pub emission: Arc<dyn Texture>,
+}
+
+impl Principled {
+    // A rough plastic of the given color, to be changed field by field.
+    pub fn creat(base_color: Arc<dyn Texture>) -> Principled {
+        Principled {
+            base_color,
+            metallic: solid(0.0),
+            roughness: solid(0.5),
+            specular: solid(0.5),
+            clearcoat: solid(0.0),
+            sheen: solid(0.0),
+            transmission: solid(0.0),
+            emission: solid(0.0),
+        }
+    }
+
+    fn lobes(&self, rec: &HitRecord) -> Lobes {
+        let scalar = |tex: &Arc<dyn Texture>| {
+            let c = tex.value(rec.u, rec.v, rec.p);
+            clamp((c.x() + c.y() + c.z()) / 3.0, 0.0, 1.0)
+        };
+        let base = (*self).base_color.value(rec.u, rec.v, rec.p);
+        let metallic = scalar(&(*self).metallic);
+        let roughness = scalar(&(*self).roughness);
+        let specular = scalar(&(*self).specular);
+        let transmission = scalar(&(*self).transmission);
+        let sheen = scalar(&(*self).sheen);
+        let white = Color { e: [1.0; 3] };
+
+        let f0 = 0.08 * specular;
+        let ior = (1.0 + f0.sqrt()) / (1.0 - f0.sqrt());
+        let alpha = roughness_to_alpha(roughness, 0.0).0;
+        let coat_alpha = roughness_to_alpha(CLEARCOAT_ROUGHNESS, 0.0).0;
+        let tint = if luminance(base) > 0.0 {
+            base / luminance(base)
+        } else {
+            white
+        };
+        let mut sqrt_base = base;
+        for c in sqrt_base.e.iter_mut() {
+            *c = c.max(0.0).sqrt();
+        }
+
+        let mut lobes = Lobes {
+            base,
+            f0: white * (f0 * (1.0 - metallic)) + base * metallic,
+            sheen: (white + tint) * (0.5 * sheen),
+            // light crosses the surface twice, once each way
+            transmission_tint: sqrt_base,
+            roughness,
+            diffuse: (1.0 - metallic) * (1.0 - transmission),
+            specular: 1.0 - (1.0 - metallic) * transmission,
+            clearcoat: 0.25 * scalar(&(*self).clearcoat),
+            transmission: (1.0 - metallic) * transmission,
+            ggx: Ggx {
+                alpha_x: alpha,
+                alpha_y: alpha,
+            },
+            coat: Ggx {
+                alpha_x: coat_alpha,
+                alpha_y: coat_alpha,
+            },
+            eta: ior,
+        };
+        // a surface that lets light through bounds a volume, seen from inside
+        // only the glass remains
+        if !rec.front_face && transmission > 0.0 {
+            lobes.diffuse = 0.0;
+            lobes.specular = 0.0;
+            lobes.clearcoat = 0.0;
+            lobes.transmission = 1.0;
+            lobes.eta = 1.0 / ior;
+        }
+        lobes
+    }
+}
+
+// The parameters at one point and the weights of the lobes they give.
+#[derive(Clone, Copy)]
+struct Lobes {
+    base: Color,
+    f0: Color,
+    sheen: Color,
+    transmission_tint: Color,
+    roughness: f64,
+    diffuse: f64,
+    specular: f64,
+    clearcoat: f64,
+    transmission: f64,
+    ggx: Ggx,
+    coat: Ggx,
+    // index behind the surface over the one in front
+    eta: f64,
+}
+
+impl Lobes {
+    // BSDF times the cosine, in the local frame with `wo` above the surface.
+    fn f(&self, wo: Vec3, wi: Vec3) -> Color {
+        let mut f = Color { e: [0.0; 3] };
+        if wo.z() <= 0.0 {
+            return f;
+        }
+        if wi.z() > 0.0 {
+            let h = unit_vector(wo + wi);
+            let cos_d = dot(wi, h);
+            if (*self).diffuse > 0.0 {
+                // Burley's diffuse, darker at grazing angles when smooth and
+                // brighter when rough
+                let fd90 = 0.5 + 2.0 * (*self).roughness * cos_d * cos_d;
+                let fl = (1.0 - wi.z()).powi(5);
+                let fv = (1.0 - wo.z()).powi(5);
+                let retro = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+                f += (*self).base * ((*self).diffuse * retro * wi.z() / PI);
+                f += (*self).sheen * ((*self).diffuse * (1.0 - cos_d).powi(5) * wi.z());
+            }
+            if (*self).specular > 0.0 && !(*self).ggx.is_smooth() {
+                let ggx = (*self).ggx;
+                let s = ggx.d(h) * ggx.g(wo, wi) / (4.0 * wo.z());
+                f += schlick((*self).f0, cos_d) * ((*self).specular * s);
+            }
+            if (*self).clearcoat > 0.0 {
+                let coat = (*self).coat;
+                let s = coat.d(h) * coat.g(wo, wi) / (4.0 * wo.z());
+                f += schlick(Color { e: [0.04; 3] }, cos_d) * ((*self).clearcoat * s);
+            }
+        }
+        if (*self).transmission > 0.0 && !(*self).ggx.is_smooth() {
+            let t = (*self).transmission * (*self).ggx.dielectric_f(wo, wi, (*self).eta);
+            f += if wi.z() < 0.0 {
+                (*self).transmission_tint * t
+            } else {
+                Color { e: [t; 3] }
+            };
+        }
+        f
+    }
+
+    // Lobes are picked in proportion to their weights.
+    fn probabilities(&self) -> [f64; 4] {
+        let w = [
+            (*self).diffuse,
+            (*self).specular,
+            (*self).clearcoat,
+            (*self).transmission,
+        ];
+        let sum: f64 = w.iter().sum();
+        let mut p = [0.0; 4];
+        for (pi, wi) in p.iter_mut().zip(w.iter()) {
+            *pi = wi / sum;
+        }
+        p
+    }
+
+    // Chance that `scatter` takes the lobes with a density: when smooth, the
+    // specular and transmission lobes are deltas and get picked apart.
+    fn continuous(&self) -> f64 {
+        let p = (*self).probabilities();
+        if (*self).ggx.is_smooth() {
+            p[0] + p[2]
+        } else {
+            1.0
+        }
+    }
+
+    // The probabilities of the lobes once `scatter` took the continuous ones.
+    fn continuous_probabilities(&self) -> [f64; 4] {
+        let mut p = (*self).probabilities();
+        let q = (*self).continuous();
+        if q < 1.0 {
+            // all zero for smooth glass, where there's no such lobe
+            p = if q > 0.0 {
+                [p[0] / q, 0.0, p[2] / q, 0.0]
+            } else {
+                [0.0; 4]
+            };
+        }
+        p
+    }
+
+    fn pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let p = (*self).continuous_probabilities();
+        let mut pdf = 0.0;
+        if wi.z() > 0.0 {
+            pdf += p[0] * wi.z() / PI;
+        }
+        // with transmission, reflections below the surface still count; a
+        // smooth lobe has no density to add
+        if p[1] > 0.0 {
+            pdf += p[1] * (*self).ggx.reflected_pdf(wo, wi);
+        }
+        if p[2] > 0.0 {
+            pdf += p[2] * (*self).coat.reflected_pdf(wo, wi);
+        }
+        if p[3] > 0.0 {
+            pdf += p[3] * (*self).ggx.dielectric_pdf(wo, wi, (*self).eta);
+        }
+        pdf
+    }
+
+    fn sample(&self, wo: Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        let [diffuse, specular, clearcoat, _] = (*self).continuous_probabilities();
+        let pick = sampler.get_1d();
+        if pick < diffuse {
+            random_cosine_direction(sampler)
+        } else if pick < diffuse + specular + clearcoat {
+            let ggx = if pick < diffuse + specular {
+                (*self).ggx
+            } else {
+                (*self).coat
+            };
+            let (u1, u2) = sampler.get_2d();
+            let h = ggx.sample_visible(wo, u1, u2);
+            reflect(Vec3 { e: [0.0; 3] } - wo, h)
+        } else {
+            (*self).ggx.sample_dielectric(wo, (*self).eta, sampler)
+        }
+    }
+}
+
+impl Lobes {
+    // One of the delta lobes of a smooth surface, picked with probability
+    // p[1] for the mirror and p[3] for the glass. Glass then reflects or
+    // refracts by its Fresnel term.
+    fn scatter_smooth(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        mirror: bool,
+        sampler: &mut dyn Sampler,
+    ) -> ScatterRecord {
+        let p = (*self).probabilities();
+        let unit_direction = unit_vector(r_in.get_dir());
+        let wo = Vec3 { e: [0.0; 3] } - unit_direction;
+        let cos = dot(wo, rec.normal);
+        let reflected = reflect(unit_direction, rec.normal);
+        let (dir, attenuation) = if mirror {
+            let f = schlick((*self).f0, cos) * ((*self).specular / p[1]);
+            (reflected, f)
+        } else {
+            let weight = (*self).transmission / p[3];
+            let r = fresnel_dielectric(cos, (*self).eta);
+            match refract_out(wo, rec.normal, (*self).eta) {
+                Some(dir) if sampler.get_1d() >= r => (
+                    dir,
+                    (*self).transmission_tint * (weight / ((*self).eta * (*self).eta)),
+                ),
+                _ => (reflected, Color { e: [weight; 3] }),
+            }
+        };
+        ScatterRecord {
+            specular_ray: Ray {
+                st: rec.p,
+                dir,
+                tm: r_in.get_time(),
+            },
+            is_specular: true,
+            attenuation,
+            pdf_ptr: None,
+        }
+    }
+}
+
+fn local_frame(r_in: Ray, rec: &HitRecord, scattered: Ray) -> (Vec3, Vec3) {
+    let mut uvw: Onb = Default::default();
+    uvw.build_from_w(rec.normal);
+    (
+        uvw.to_local(Vec3 { e: [0.0; 3] } - unit_vector(r_in.get_dir())),
+        uvw.to_local(unit_vector(scattered.get_dir())),
+    )
+}
+
+struct PrincipledPdf {
+    uvw: Onb,
+    wo: Vec3,
+    lobes: Lobes,
+}
+
+impl Pdf for PrincipledPdf {
+    fn value(&self, dir: Vec3) -> f64 {
+        let wi = (*self).uvw.to_local(unit_vector(dir));
+        (*self).lobes.pdf((*self).wo, wi)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        (*self)
+            .uvw
+            .local_vec((*self).lobes.sample((*self).wo, sampler))
+    }
+}
+
+impl Material for Principled {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let lobes = (*self).lobes(rec);
+        if lobes.ggx.is_smooth() {
+            let u = sampler.get_1d() - lobes.continuous();
+            if u >= 0.0 {
+                let mirror = u < lobes.probabilities()[1];
+                return Some(lobes.scatter_smooth(r_in, rec, mirror, sampler));
+            }
+        }
+        let mut uvw: Onb = Default::default();
+        uvw.build_from_w(rec.normal);
+        Some(ScatterRecord {
+            specular_ray: Default::default(),
+            is_specular: false,
+            attenuation: lobes.base,
+            pdf_ptr: Some(Box::new(PrincipledPdf {
+                uvw,
+                wo: uvw.to_local(Vec3 { e: [0.0; 3] } - unit_vector(r_in.get_dir())),
+                lobes,
+            })),
+        })
+    }
+
+    fn emitted(&self, _r_in: Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        if rec.front_face {
+            (*self).emission.value(u, v, p)
+        } else {
+            Color { e: [0.0; 3] }
+        }
+    }
+
+    fn scattering(&self, r_in: Ray, rec: &HitRecord, scattered: Ray, _attenuation: Color) -> Color {
+        let (wo, wi) = local_frame(r_in, rec, scattered);
+        // only seen when `scatter` took the continuous lobes
+        let lobes = (*self).lobes(rec);
+        let q = lobes.continuous();
+        if q > 0.0 {
+            lobes.f(wo, wi) / q
+        } else {
+            Color { e: [0.0; 3] }
+        }
+    }
+
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        let (wo, wi) = local_frame(r_in, rec, scattered);
+        (*self).lobes(rec).pdf(wo, wi)
+    }
+
+    fn emission(&self) -> Color {
+        average_value(&*(*self).emission)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::microfacet::{
+        assert_energy, integrate_sphere, plane_hit, sampled_flux, scattered_flux,
+    };
+    use crate::sampler::IndependentSampler;
+
+    fn material(metallic: f64, roughness: f64, clearcoat: f64, transmission: f64) -> Principled {
+        let mut mat = Principled::creat(solid(0.8));
+        mat.metallic = solid(metallic);
+        mat.roughness = solid(roughness);
+        mat.clearcoat = solid(clearcoat);
+        mat.transmission = solid(transmission);
+        mat
+    }
+
+    const WO: [[f64; 3]; 4] = [
+        [0.0, 0.1, 1.0],
+        [0.7, 0.0, 0.7],
+        [0.0, 0.2, -1.0],
+        [0.3, 0.3, -0.9],
+    ];
+
+    // The integral of `scattering` stands for the continuous lobes only, the
+    // delta ones add `smooth_energy`.
+    fn check_lobes(mat: &Principled, smooth_energy: impl Fn(&Lobes, f64) -> f64) {
+        for wo in WO.iter() {
+            let (r_in, rec) = plane_hit(unit_vector(Vec3 { e: *wo }), mat);
+            let lobes = mat.lobes(&rec);
+            let cos = dot(unit_vector(Vec3 { e: *wo }), rec.normal);
+            let continuous = integrate_sphere(|wi| scattered_flux(mat, r_in, &rec, lobes.eta, wi));
+            let energy = continuous * lobes.continuous() + smooth_energy(&lobes, cos);
+            assert_energy(energy, |sampler| {
+                sampled_flux(mat, r_in, &rec, lobes.eta, sampler)
+            });
+            // masking loses some of it, and past the critical angle the
+            // inside reflects everything
+            assert!(energy > 0.6, "{} {:?}", energy, wo);
+            let total_pdf = integrate_sphere(|wi| {
+                let scattered = Ray {
+                    st: rec.p,
+                    dir: wi,
+                    tm: 0.0,
+                };
+                mat.scattering_pdf(r_in, &rec, scattered)
+            });
+            if lobes.continuous() > 0.0 {
+                assert!(total_pdf <= 1.0 + 1e-2 && total_pdf > 0.9, "{}", total_pdf);
+            }
+        }
+    }
+
+    #[test]
+    fn rough_lobes_keep_at_most_the_incoming_energy() {
+        for mat in [
+            material(0.0, 0.5, 0.0, 0.0),
+            material(1.0, 0.3, 0.0, 0.0),
+            material(0.0, 0.4, 0.0, 1.0),
+            material(0.3, 0.5, 1.0, 0.5),
+        ]
+        .iter()
+        {
+            check_lobes(mat, |_, _| 0.0);
+        }
+    }
+
+    #[test]
+    fn smooth_lobes_are_sampled_apart() {
+        for mat in [
+            material(0.0, 0.0, 0.0, 0.0),
+            material(0.0, 0.0, 0.0, 1.0),
+            material(0.3, 0.0, 1.0, 0.5),
+        ]
+        .iter()
+        {
+            // mirror reflection plus glass which, without absorption, keeps
+            // all of it
+            check_lobes(mat, |lobes, cos| {
+                let glass = lobes.transmission * luminance(lobes.transmission_tint);
+                let r = fresnel_dielectric(cos.abs(), lobes.eta);
+                lobes.specular * luminance(schlick(lobes.f0, cos))
+                    + lobes.transmission * r
+                    + glass * (1.0 - r)
+            });
+        }
+    }
+
+    #[test]
+    fn smooth_metal_is_a_mirror() {
+        let mat = material(1.0, 0.0, 0.0, 0.0);
+        let mut sampler = IndependentSampler::creat(4);
+        let wo = unit_vector(Vec3 { e: [0.6, 0.0, 0.8] });
+        let (r_in, rec) = plane_hit(wo, &mat);
+        for i in 0..16 {
+            sampler.start_pixel_sample(0, 0, i);
+            let srec = mat.scatter(r_in, &rec, &mut sampler).unwrap();
+            assert!(srec.is_specular);
+            let dir = srec.specular_ray.get_dir();
+            assert!(
+                (dir - Vec3 {
+                    e: [-0.6, 0.0, 0.8]
+                })
+                .length()
+                    < 1e-9
+            );
+            let expected = schlick(Color { e: [0.8; 3] }, 0.8);
+            assert!((srec.attenuation - expected).length() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use super::microfacet::{
+    fresnel_dielectric, refract_out, roughness_to_alpha, Ggx, GgxDielectricPdf,
+};
+use super::{Material, ScatterRecord};
+use crate::basic::{
+    onb::Onb,
+    ray::Ray,
+    vec3::{dot, reflect, unit_vector, Color, Vec3},
+};
+use crate::hittable::HitRecord;
+use crate::sampler::Sampler;
+
+// Frosted glass: GGX microfacets both reflecting and refracting, with the
+// exact dielectric Fresnel term. Light travelling inside is absorbed
+// following Beer's law, which is what gives thick colored glass its tint.
+#[derive(Clone, Copy, Debug)]
+pub struct RoughDielectric {
+    pub ir: f64,
+    pub ggx: Ggx,
+    // absorption coefficient per unit of distance inside
+    pub absorption: Color,
+}
+
+impl RoughDielectric {
+    // `roughness` in [0, 1], 0 being clear glass.
+    pub fn creat(ir: f64, roughness: f64, absorption: Color) -> RoughDielectric {
+        let (alpha_x, alpha_y) = roughness_to_alpha(roughness, 0.0);
+        RoughDielectric {
+            ir,
+            ggx: Ggx { alpha_x, alpha_y },
+            absorption,
+        }
+    }
+
+    // index behind the surface over the one in front
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face {
+            (*self).ir
+        } else {
+            1.0 / (*self).ir
+        }
+    }
+
+    // Fraction of the light left after crossing the inside up to `rec`.
+    fn transmittance(&self, r_in: Ray, rec: &HitRecord) -> Color {
+        if rec.front_face {
+            return Color { e: [1.0; 3] };
+        }
+        let distance = rec.t * r_in.get_dir().length();
+        let mut e = [0.0; 3];
+        for (i, t) in e.iter_mut().enumerate() {
+            *t = (-(*self).absorption.e[i] * distance).exp();
+        }
+        Color { e }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let eta = (*self).eta(rec);
+        let unit_direction = unit_vector(r_in.get_dir());
+        let wo = Vec3 { e: [0.0; 3] } - unit_direction;
+        let transmittance = (*self).transmittance(r_in, rec);
+        if !(*self).ggx.is_smooth() {
+            return Some(ScatterRecord {
+                specular_ray: Default::default(),
+                is_specular: false,
+                attenuation: transmittance,
+                pdf_ptr: Some(Box::new(GgxDielectricPdf::creat(
+                    rec.normal,
+                    wo,
+                    (*self).ggx,
+                    eta,
+                ))),
+            });
+        }
+
+        // smooth: reflect or refract in proportion to the Fresnel term, which
+        // leaves only the radiance scaling of refraction as weight
+        let r = fresnel_dielectric(dot(wo, rec.normal), eta);
+        let (direction, weight) = if sampler.get_1d() < r {
+            (reflect(unit_direction, rec.normal), 1.0)
+        } else {
+            match refract_out(wo, rec.normal, eta) {
+                Some(dir) => (dir, 1.0 / (eta * eta)),
+                None => (reflect(unit_direction, rec.normal), 1.0),
+            }
+        };
+        Some(ScatterRecord {
+            specular_ray: Ray {
+                st: rec.p,
+                dir: direction,
+                tm: r_in.get_time(),
+            },
+            is_specular: true,
+            attenuation: transmittance * weight,
+            pdf_ptr: None,
+        })
+    }
+
+    fn scattering(&self, r_in: Ray, rec: &HitRecord, scattered: Ray, _attenuation: Color) -> Color {
+        let mut uvw: Onb = Default::default();
+        uvw.build_from_w(rec.normal);
+        let wo = uvw.to_local(Vec3 { e: [0.0; 3] } - unit_vector(r_in.get_dir()));
+        let wi = uvw.to_local(unit_vector(scattered.get_dir()));
+        (*self).transmittance(r_in, rec) * (*self).ggx.dielectric_f(wo, wi, (*self).eta(rec))
+    }
+
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        let mut uvw: Onb = Default::default();
+        uvw.build_from_w(rec.normal);
+        let wo = uvw.to_local(Vec3 { e: [0.0; 3] } - unit_vector(r_in.get_dir()));
+        let wi = uvw.to_local(unit_vector(scattered.get_dir()));
+        (*self).ggx.dielectric_pdf(wo, wi, (*self).eta(rec))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::microfacet::{
+        assert_energy, integrate_sphere, plane_hit, sampled_flux, scattered_flux,
+    };
+
+    #[test]
+    fn rough_glass_keeps_at_most_the_incoming_energy() {
+        for roughness in [0.3, 0.6].iter() {
+            let glass = RoughDielectric::creat(1.5, *roughness, Color { e: [0.0; 3] });
+            for wo in [
+                [0.0, 0.1, 1.0],
+                [0.7, 0.0, 0.7],
+                [0.0, 0.2, -1.0],
+                [0.3, 0.3, -0.9],
+            ]
+            .iter()
+            {
+                let (r_in, rec) = plane_hit(unit_vector(Vec3 { e: *wo }), &glass);
+                let eta = glass.eta(&rec);
+                let energy = integrate_sphere(|wi| scattered_flux(&glass, r_in, &rec, eta, wi));
+                assert_energy(energy, |sampler| {
+                    sampled_flux(&glass, r_in, &rec, eta, sampler)
+                });
+                // masking loses some of it, and past the critical angle
+                // the inside reflects everything
+                assert!(energy > 0.8, "{} {:?}", energy, wo);
+                let total_pdf = integrate_sphere(|wi| {
+                    let scattered = Ray {
+                        st: rec.p,
+                        dir: wi,
+                        tm: 0.0,
+                    };
+                    glass.scattering_pdf(r_in, &rec, scattered)
+                });
+                assert!(total_pdf <= 1.0 + 1e-2, "{}", total_pdf);
+            }
+        }
+    }
+}
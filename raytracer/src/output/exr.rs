@@ -0,0 +1,156 @@
+use crate::basic::vec3::Color;
+use std::io::{self, Write};
+
+// Minimal OpenEXR writer: single part, scanline, uncompressed, 32-bit float
+// R, G and B channels.
+
+const FLOAT: i32 = 2;
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+fn ints(values: &[i32]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|v| v.to_le_bytes().to_vec())
+        .collect()
+}
+
+pub fn write(
+    out: &mut impl Write,
+    width: usize,
+    height: usize,
+    pixels: &[Color],
+) -> io::Result<()> {
+    let mut header: Vec<u8> = vec![0x76, 0x2f, 0x31, 0x01];
+    header.extend_from_slice(&2i32.to_le_bytes());
+
+    // channels must be listed (and stored) in alphabetical order
+    let mut channels: Vec<u8> = Default::default();
+    for name in &["B", "G", "R"] {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&FLOAT.to_le_bytes());
+        // pLinear and three reserved bytes
+        channels.extend_from_slice(&[0; 4]);
+        channels.extend_from_slice(&ints(&[1, 1]));
+    }
+    channels.push(0);
+    attribute(&mut header, "channels", "chlist", &channels);
+    attribute(&mut header, "compression", "compression", &[0]);
+    let window = ints(&[0, 0, width as i32 - 1, height as i32 - 1]);
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    let mut center = 0f32.to_le_bytes().to_vec();
+    center.extend_from_slice(&0f32.to_le_bytes());
+    attribute(&mut header, "screenWindowCenter", "v2f", &center);
+    attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+    out.write_all(&header)?;
+
+    let line_size = 3 * 4 * width;
+    let first_line = (header.len() + 8 * height) as u64;
+    for y in 0..height as u64 {
+        out.write_all(&(first_line + y * (8 + line_size as u64)).to_le_bytes())?;
+    }
+
+    // EXR scanlines go top to bottom, `pixels` starts at the bottom row
+    for y in 0..height {
+        out.write_all(&(y as i32).to_le_bytes())?;
+        out.write_all(&(line_size as i32).to_le_bytes())?;
+        let row = &pixels[(height - 1 - y) * width..(height - y) * width];
+        for channel in (0..3).rev() {
+            for c in row {
+                out.write_all(&(c.e[channel] as f32).to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        let mut b = [0; 4];
+        b.copy_from_slice(&bytes[at..at + 4]);
+        u32::from_le_bytes(b)
+    }
+
+    fn f32_at(bytes: &[u8], at: usize) -> f32 {
+        f32::from_bits(u32_at(bytes, at))
+    }
+
+    fn u64_at(bytes: &[u8], at: usize) -> u64 {
+        let mut b = [0; 8];
+        b.copy_from_slice(&bytes[at..at + 8]);
+        u64::from_le_bytes(b)
+    }
+
+    // Skips the attributes: name, type, size and value, up to the empty name.
+    fn header_len(bytes: &[u8]) -> usize {
+        let mut at = 8;
+        loop {
+            let name_end = at + bytes[at..].iter().position(|b| *b == 0).unwrap();
+            if name_end == at {
+                return at + 1;
+            }
+            let kind_end =
+                name_end + 1 + bytes[name_end + 1..].iter().position(|b| *b == 0).unwrap();
+            at = kind_end + 5 + u32_at(bytes, kind_end + 1) as usize;
+        }
+    }
+
+    #[test]
+    fn scanlines_go_top_down_with_channels_in_bgr_order() {
+        let (width, height) = (3, 2);
+        let pixels: Vec<Color> = (0..width * height)
+            .map(|i| Color {
+                e: [i as f64, i as f64 + 0.25, i as f64 + 0.5],
+            })
+            .collect();
+        let mut bytes: Vec<u8> = Default::default();
+        write(&mut bytes, width, height, &pixels).unwrap();
+
+        assert_eq!(bytes[..4], [0x76, 0x2f, 0x31, 0x01]);
+        assert_eq!(u32_at(&bytes, 4), 2);
+        let header = header_len(&bytes);
+        let line_size = 3 * 4 * width;
+        for y in 0..height {
+            let offset = u64_at(&bytes, header + 8 * y) as usize;
+            assert_eq!(offset, header + 8 * height + y * (8 + line_size));
+        }
+        assert_eq!(bytes.len(), header + 8 * height + height * (8 + line_size));
+
+        // the first scanline is the top row, the last one of `pixels`
+        let line = u64_at(&bytes, header) as usize;
+        assert_eq!(u32_at(&bytes, line), 0);
+        assert_eq!(u32_at(&bytes, line + 4) as usize, line_size);
+        let top = &pixels[(height - 1) * width..];
+        for (channel, stored) in [2, 1, 0].iter().enumerate() {
+            for (x, c) in top.iter().enumerate() {
+                let at = line + 8 + 4 * (channel * width + x);
+                assert!((f32_at(&bytes, at) - c.e[*stored] as f32).abs() < 1e-6);
+            }
+        }
+    }
+}
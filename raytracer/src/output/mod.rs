@@ -0,0 +1,189 @@
+mod exr;
+pub mod tonemap;
+
+use crate::basic::{clamp, vec3::Color};
+use image::{codecs::hdr::HdrEncoder, ImageBuffer, ImageFormat, ImageOutputFormat, Rgb, RgbImage};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use tonemap::{srgb_encode, ToneMapping};
+
+pub enum Format {
+    // 8-bit formats go through `image` after tone mapping and sRGB encoding
+    Ldr(ImageOutputFormat),
+    // float formats keep the linear radiance untouched
+    Pfm,
+    Hdr,
+    Exr,
+}
+
+impl Format {
+    pub fn from_extension(ext: &str, quality: u8) -> Option<Format> {
+        match ext.to_ascii_lowercase().as_str() {
+            "pfm" => Some(Format::Pfm),
+            "hdr" => Some(Format::Hdr),
+            "exr" => Some(Format::Exr),
+            ext => match ImageFormat::from_extension(ext) {
+                Some(ImageFormat::Jpeg) => Some(Format::Ldr(ImageOutputFormat::Jpeg(quality))),
+                Some(f @ ImageFormat::Png)
+                | Some(f @ ImageFormat::Bmp)
+                | Some(f @ ImageFormat::Tga) => Some(Format::Ldr(ImageOutputFormat::from(f))),
+                _ => None,
+            },
+        }
+    }
+}
+
+fn write_color(
+    pixel_color: Color,
+    tone: &ToneMapping,
+    img: &mut RgbImage,
+    x: usize,
+    y: usize,
+    image_height: usize,
+) {
+    let pixel = (*img).get_pixel_mut(x as u32, (image_height - y - 1) as u32);
+    let mapped = tone.apply(pixel_color);
+    let r_color = srgb_encode(mapped.x());
+    let g_color = srgb_encode(mapped.y());
+    let b_color = srgb_encode(mapped.z());
+    let res: [u8; 3] = [
+        (256.0 * clamp(r_color, 0.0, 0.999)) as u8,
+        (256.0 * clamp(g_color, 0.0, 0.999)) as u8,
+        (256.0 * clamp(b_color, 0.0, 0.999)) as u8,
+    ];
+    *pixel = image::Rgb(res);
+}
+
+fn to_io(e: image::ImageError) -> io::Error {
+    match e {
+        image::ImageError::IoError(e) => e,
+        e => io::Error::new(io::ErrorKind::Other, e.to_string()),
+    }
+}
+
+// Portable float map, rows are stored bottom to top like `pixels`.
+fn write_pfm(
+    out: &mut impl Write,
+    width: usize,
+    height: usize,
+    pixels: &[Color],
+) -> io::Result<()> {
+    write!(out, "PF\n{} {}\n-1.0\n", width, height)?;
+    for c in pixels {
+        for v in &c.e {
+            out.write_all(&(*v as f32).to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+fn write_hdr(
+    out: &mut impl Write,
+    width: usize,
+    height: usize,
+    pixels: &[Color],
+) -> io::Result<()> {
+    let mut data: Vec<Rgb<f32>> = Vec::with_capacity(width * height);
+    for y in (0..height).rev() {
+        for c in &pixels[y * width..(y + 1) * width] {
+            // RGBE has no sign bit and no way to store NaN
+            let v = |x: f64| {
+                if x.is_finite() {
+                    x.max(0.0) as f32
+                } else {
+                    0.0
+                }
+            };
+            data.push(Rgb([v(c.x()), v(c.y()), v(c.z())]));
+        }
+    }
+    HdrEncoder::new(out)
+        .encode(&data, width, height)
+        .map_err(to_io)
+}
+
+// False colors for per-pixel sample counts, from black for no samples through
+// blue and red to yellow at `max`.
+pub fn heatmap(counts: &[usize], max: usize) -> Vec<Color> {
+    let stops = [
+        Color { e: [0.0, 0.0, 0.0] },
+        Color { e: [0.0, 0.0, 1.0] },
+        Color { e: [1.0, 0.0, 0.0] },
+        Color { e: [1.0, 1.0, 0.0] },
+    ];
+    counts
+        .iter()
+        .map(|&n| {
+            let t = clamp(n as f64 / max as f64, 0.0, 1.0) * (stops.len() - 1) as f64;
+            let k = (t as usize).min(stops.len() - 2);
+            let f = t - k as f64;
+            stops[k] * (1.0 - f) + stops[k + 1] * f
+        })
+        .collect()
+}
+
+// Writes the linear radiance `pixels` (scanline order, bottom row first) to
+// `path`.
+// `tone` only applies to the 8-bit formats, the others keep the radiance.
+pub fn write(
+    path: &str,
+    format: &Format,
+    tone: &ToneMapping,
+    width: usize,
+    height: usize,
+    pixels: &[Color],
+) -> io::Result<()> {
+    // written next to the target and renamed, so that a viewer watching it
+    // never sees half an image
+    let tmp = format!("{}.part", path);
+    {
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        match format {
+            Format::Ldr(f) => {
+                let mut img: RgbImage = ImageBuffer::new(width as u32, height as u32);
+                for y in 0..height {
+                    for x in 0..width {
+                        write_color(pixels[y * width + x], tone, &mut img, x, y, height);
+                    }
+                }
+                image::DynamicImage::ImageRgb8(img)
+                    .write_to(&mut out, f.clone())
+                    .map_err(to_io)?;
+            }
+            Format::Pfm => write_pfm(&mut out, width, height, pixels)?,
+            Format::Hdr => write_hdr(&mut out, width, height, pixels)?,
+            Format::Exr => exr::write(&mut out, width, height, pixels)?,
+        }
+        out.flush()?;
+    }
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pfm_stores_little_endian_floats_bottom_row_first() {
+        let pixels: Vec<Color> = (0..4)
+            .map(|i| Color {
+                e: [i as f64, -0.5 * i as f64, 1e3],
+            })
+            .collect();
+        let mut bytes: Vec<u8> = Default::default();
+        write_pfm(&mut bytes, 2, 2, &pixels).unwrap();
+
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(bytes[..header.len()], header[..]);
+        let data = &bytes[header.len()..];
+        assert_eq!(data.len(), 4 * 3 * 4);
+        for (i, c) in pixels.iter().enumerate() {
+            for channel in 0..3 {
+                let at = 4 * (3 * i + channel);
+                let mut b = [0; 4];
+                b.copy_from_slice(&data[at..at + 4]);
+                assert!((f32::from_le_bytes(b) - c.e[channel] as f32).abs() < 1e-6);
+            }
+        }
+    }
+}
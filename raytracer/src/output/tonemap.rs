@@ -0,0 +1,76 @@
+use crate::basic::{clamp, vec3::Color};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMap {
+    // hard clip at 1
+    Clamp,
+    // L / (1 + L) on luminance
+    Reinhard,
+    // Reinhard with the given luminance mapped to white
+    ExtendedReinhard(f64),
+    // Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ToneMapping {
+    pub operator: ToneMap,
+    // in stops, applied before the operator
+    pub exposure: f64,
+}
+
+pub fn luminance(c: Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
+// Scales `c` so that its luminance becomes `f(luminance)`, which keeps the hue.
+fn map_luminance(c: Color, f: impl Fn(f64) -> f64) -> Color {
+    let l = luminance(c);
+    if l <= 0.0 {
+        Color { e: [0.0; 3] }
+    } else {
+        c * (f(l) / l)
+    }
+}
+
+fn aces(x: f64) -> f64 {
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+// sRGB opto-electronic transfer function
+pub fn srgb_encode(x: f64) -> f64 {
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl ToneMapping {
+    // Maps linear scene radiance to linear display values in [0, 1].
+    pub fn apply(&self, c: Color) -> Color {
+        let mut c = c * 2f64.powf((*self).exposure);
+        for v in c.e.iter_mut() {
+            if !v.is_finite() || *v < 0.0 {
+                *v = 0.0;
+            }
+        }
+        let c = match (*self).operator {
+            ToneMap::Clamp => c,
+            ToneMap::Reinhard => map_luminance(c, |l| l / (1.0 + l)),
+            ToneMap::ExtendedReinhard(white) => {
+                map_luminance(c, |l| l * (1.0 + l / (white * white)) / (1.0 + l))
+            }
+            ToneMap::Aces => Color {
+                e: [aces(c.x()), aces(c.y()), aces(c.z())],
+            },
+        };
+        Color {
+            e: [
+                clamp(c.x(), 0.0, 1.0),
+                clamp(c.y(), 0.0, 1.0),
+                clamp(c.z(), 0.0, 1.0),
+            ],
+        }
+    }
+}
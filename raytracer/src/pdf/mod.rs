@@ -1,17 +1,28 @@
 use crate::basic::{
     onb::Onb,
-    vec3::{dot, random_double, unit_vector, Vec3},
+    vec3::{dot, random_unit_vector, unit_vector, Point3, Vec3},
 };
+use crate::hittable::Hittable;
+use crate::sampler::Sampler;
 use std::f64::consts::PI;
 
 pub trait Pdf {
     fn value(&self, dir: Vec3) -> f64;
-    fn generate(&self) -> Vec3;
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3;
 }
 
-pub fn random_cosine_direction() -> Vec3 {
-    let r1 = random_double();
-    let r2 = random_double();
+impl Pdf for Box<dyn Pdf> {
+    fn value(&self, dir: Vec3) -> f64 {
+        (**self).value(dir)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        (**self).generate(sampler)
+    }
+}
+
+pub fn random_cosine_direction(sampler: &mut dyn Sampler) -> Vec3 {
+    let (r1, r2) = sampler.get_2d();
     let z = (1.0 - r2).sqrt();
 
     let phi = 2.0 * PI * r1;
@@ -20,6 +31,16 @@ pub fn random_cosine_direction() -> Vec3 {
     Vec3 { e: [x, y, z] }
 }
 
+pub fn random_to_sphere(radius: f64, distance_squared: f64, sampler: &mut dyn Sampler) -> Vec3 {
+    let (r1, r2) = sampler.get_2d();
+    let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).max(0.0).sqrt() - 1.0);
+
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * (1.0 - z * z).sqrt();
+    let y = phi.sin() * (1.0 - z * z).sqrt();
+    Vec3 { e: [x, y, z] }
+}
+
 pub struct CosinePdf {
     uvw: Onb,
 }
@@ -44,7 +65,75 @@ impl Pdf for CosinePdf {
         }
     }
 
-    fn generate(&self) -> Vec3 {
-        (*self).uvw.local_vec(random_cosine_direction())
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        (*self).uvw.local_vec(random_cosine_direction(sampler))
+    }
+}
+
+// Every direction equally likely.
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _dir: Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        random_unit_vector(sampler)
+    }
+}
+
+pub struct HittablePdf<'a, T: Hittable> {
+    o: Point3,
+    ptr: &'a T,
+}
+
+impl<'a, T: Hittable> HittablePdf<'a, T> {
+    pub fn creat(p: &'a T, origin: Point3) -> HittablePdf<'a, T> {
+        HittablePdf { o: origin, ptr: p }
+    }
+}
+
+impl<'a, T: Hittable> Pdf for HittablePdf<'a, T> {
+    fn value(&self, dir: Vec3) -> f64 {
+        (*self).ptr.pdf_value((*self).o, dir)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        (*self).ptr.random((*self).o, sampler)
+    }
+}
+
+pub struct MixturePdf<T: Pdf, U: Pdf> {
+    p0: T,
+    p1: U,
+    weight: f64,
+}
+
+impl<T: Pdf, U: Pdf> MixturePdf<T, U> {
+    pub fn creat(p0: T, p1: U) -> MixturePdf<T, U> {
+        MixturePdf::creat_weighted(p0, 1.0, p1, 1.0)
+    }
+
+    pub fn creat_weighted(p0: T, w0: f64, p1: U, w1: f64) -> MixturePdf<T, U> {
+        MixturePdf {
+            p0,
+            p1,
+            weight: w0 / (w0 + w1),
+        }
+    }
+}
+
+impl<T: Pdf, U: Pdf> Pdf for MixturePdf<T, U> {
+    fn value(&self, dir: Vec3) -> f64 {
+        (*self).weight * (*self).p0.value(dir) + (1.0 - (*self).weight) * (*self).p1.value(dir)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        if sampler.get_1d() < (*self).weight {
+            (*self).p0.generate(sampler)
+        } else {
+            (*self).p1.generate(sampler)
+        }
     }
 }
@@ -0,0 +1,74 @@
+use super::{hash, hash_to_unit, mix, permutation_element, Sampler};
+
+const PRIMES: [u64; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+// Radical inverse of `a` whose digits are shuffled by a permutation that
+// depends on all the less significant digits, i.e. Owen scrambling.
+fn owen_scrambled_radical_inverse(base: u64, a: u64, seed: u64) -> f64 {
+    let mut a = a;
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_m = 1.0;
+    let mut prefix: u64 = 0;
+    let mut ret = 0.0;
+    while 1.0 - (base - 1) as f64 * inv_base_m < 1.0 {
+        let next = a / base;
+        let digit = permutation_element(
+            (a - next * base) as u32,
+            base as u32,
+            mix(seed ^ prefix) as u32,
+        );
+        prefix = prefix.wrapping_mul(base).wrapping_add(digit as u64);
+        inv_base_m *= inv_base;
+        ret += digit as f64 * inv_base_m;
+        a = next;
+    }
+    ret.min(1.0 - f64::EPSILON / 2.0)
+}
+
+// Halton points, with the sequence of every pixel scrambled independently.
+// Dimensions past the prime table fall back to independent random numbers.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: (usize, usize),
+    index: usize,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn creat(seed: u64) -> HaltonSampler {
+        HaltonSampler {
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        (*self).pixel = (x, y);
+        (*self).index = index;
+        (*self).dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let h = hash(&[
+            (*self).seed,
+            (*self).pixel.0 as u64,
+            (*self).pixel.1 as u64,
+            (*self).dimension as u64,
+        ]);
+        let ret = match PRIMES.get((*self).dimension) {
+            Some(base) => owen_scrambled_radical_inverse(*base, (*self).index as u64, h),
+            None => hash_to_unit(hash(&[h, (*self).index as u64])),
+        };
+        (*self).dimension += 1;
+        ret
+    }
+}
@@ -0,0 +1,26 @@
+use super::{hash, Sampler};
+use rand::prelude::*;
+
+pub struct IndependentSampler {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl IndependentSampler {
+    pub fn creat(seed: u64) -> IndependentSampler {
+        IndependentSampler {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        (*self).rng =
+            StdRng::seed_from_u64(hash(&[(*self).seed, x as u64, y as u64, index as u64]));
+    }
+    fn get_1d(&mut self) -> f64 {
+        (*self).rng.gen::<f64>()
+    }
+}
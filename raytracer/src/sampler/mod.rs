@@ -0,0 +1,201 @@
+pub mod halton;
+pub mod independent;
+pub mod sobol;
+pub mod stratified;
+
+pub use halton::HaltonSampler;
+pub use independent::IndependentSampler;
+pub use sobol::SobolSampler;
+pub use stratified::StratifiedSampler;
+
+// Source of the random numbers used while rendering. Every sample of every
+// pixel gets its own stream, so a render only depends on the seed and not on
+// which thread traced which pixel.
+//
+// Each `get_1d`/`get_2d` call hands out the next dimension(s) of the current
+// sample, so callers should draw in the same order for every sample.
+pub trait Sampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize);
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> (f64, f64) {
+        let a = (*self).get_1d();
+        let b = (*self).get_1d();
+        (a, b)
+    }
+}
+
+// Draws nothing: every value is the middle of [0, 1) and no dimension is used
+// up. For work that must not shift the samples of the path around it, like
+// the hits needed to evaluate a pdf.
+pub struct NullSampler;
+
+impl Sampler for NullSampler {
+    fn start_pixel_sample(&mut self, _x: usize, _y: usize, _index: usize) {}
+    fn get_1d(&mut self) -> f64 {
+        0.5
+    }
+}
+
+// splitmix64 finalizer
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+pub fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e37_79b9_7f4a_7c15, |h, v| {
+        mix(h ^ v.wrapping_add(0x9e37_79b9_7f4a_7c15))
+    })
+}
+
+// Uniform float in [0, 1) from the top 53 bits of a hash.
+pub fn hash_to_unit(h: u64) -> f64 {
+    (h >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+}
+
+// The `i`-th element of a random permutation of 0..n picked by `seed`
+// (Kensler, "Correlated Multi-Jittered Sampling").
+pub fn permutation_element(i: u32, n: u32, seed: u32) -> u32 {
+    let mut i = i;
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            break;
+        }
+    }
+    (i.wrapping_add(seed)) % n
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+// `samples_per_pixel` is the total number of samples each pixel will take,
+// the stratified and Sobol patterns are laid out for that many.
+pub fn creat(kind: SamplerKind, samples_per_pixel: usize, seed: u64) -> Box<dyn Sampler> {
+    match kind {
+        SamplerKind::Independent => Box::new(IndependentSampler::creat(seed)),
+        SamplerKind::Stratified => Box::new(StratifiedSampler::creat(samples_per_pixel, seed)),
+        SamplerKind::Halton => Box::new(HaltonSampler::creat(seed)),
+        SamplerKind::Sobol => Box::new(SobolSampler::creat(samples_per_pixel, seed)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [SamplerKind; 4] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ];
+
+    // The first `dimensions` values of every sample of pixel (x, y).
+    fn draw(
+        sampler: &mut dyn Sampler,
+        x: usize,
+        y: usize,
+        spp: usize,
+        dimensions: usize,
+    ) -> Vec<f64> {
+        let mut ret = vec![];
+        for s in 0..spp {
+            sampler.start_pixel_sample(x, y, s);
+            for _ in 0..dimensions {
+                ret.push(sampler.get_1d());
+            }
+        }
+        ret
+    }
+
+    #[test]
+    fn values_are_in_the_unit_interval() {
+        for kind in KINDS.iter() {
+            let mut sampler = creat(*kind, 16, 3);
+            for y in 0..4 {
+                for x in 0..4 {
+                    for v in draw(&mut *sampler, x, y, 16, 80) {
+                        assert!((0.0..1.0).contains(&v), "{:?} gave {}", kind, v);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pixel_samples_are_stratified() {
+        let spp = 16;
+        for kind in [
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ]
+        .iter()
+        {
+            let mut sampler = creat(*kind, spp, 5);
+            for (x, y) in [(0, 0), (7, 3), (100, 42)].iter() {
+                // every 1/16 of the first dimension gets one sample
+                let mut hits = vec![0; spp];
+                for v in draw(&mut *sampler, *x, *y, spp, 1) {
+                    hits[(v * spp as f64) as usize] += 1;
+                }
+                assert!(hits.iter().all(|h| *h == 1), "{:?} {:?}", kind, hits);
+            }
+        }
+        // and every cell of a 4 x 4 grid for the 2D ones
+        for kind in [SamplerKind::Stratified, SamplerKind::Sobol].iter() {
+            let mut sampler = creat(*kind, spp, 5);
+            let mut hits = vec![0; spp];
+            for s in 0..spp {
+                sampler.start_pixel_sample(2, 9, s);
+                let (a, b) = sampler.get_2d();
+                hits[(b * 4.0) as usize * 4 + (a * 4.0) as usize] += 1;
+            }
+            assert!(hits.iter().all(|h| *h == 1), "{:?} {:?}", kind, hits);
+        }
+    }
+
+    #[test]
+    fn samples_only_depend_on_the_seed_and_pixel_sample() {
+        for kind in KINDS.iter() {
+            let mut a = creat(*kind, 16, 11);
+            let mut b = creat(*kind, 16, 11);
+            let first = draw(&mut *a, 5, 6, 16, 10);
+            // visiting another pixel in between changes nothing
+            draw(&mut *b, 1, 2, 4, 10);
+            assert_eq!(first, draw(&mut *b, 5, 6, 16, 10), "{:?}", kind);
+            let mut c = creat(*kind, 16, 12);
+            assert_ne!(first, draw(&mut *c, 5, 6, 16, 10), "{:?}", kind);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use super::{hash, mix, permutation_element, Sampler};
+
+// Owen scrambling of a 32-bit fixed point value (Burley, "Practical
+// Hash-based Owen Scrambling").
+fn owen_scramble(v: u32, seed: u32) -> u32 {
+    let mut v = v.reverse_bits();
+    v ^= v.wrapping_mul(0x3d20_adea);
+    v = v.wrapping_add(seed);
+    v = v.wrapping_mul((seed >> 16) | 1);
+    v ^= v.wrapping_mul(0x0552_6c56);
+    v ^= v.wrapping_mul(0x53a2_2864);
+    v.reverse_bits()
+}
+
+fn to_unit(v: u32) -> f64 {
+    v as f64 / 4_294_967_296.0
+}
+
+// The first two Sobol dimensions, Owen scrambled, padded to any number of
+// dimensions by shuffling the sample order per pixel and dimension. Works
+// best with a power of two samples per pixel.
+pub struct SobolSampler {
+    samples_per_pixel: usize,
+    // generator matrix columns of the second dimension, the first is the
+    // identity (van der Corput)
+    matrix: [u32; 32],
+    seed: u64,
+    pixel: (usize, usize),
+    index: usize,
+    dimension: usize,
+}
+
+impl SobolSampler {
+    pub fn creat(samples_per_pixel: usize, seed: u64) -> SobolSampler {
+        let mut matrix = [0u32; 32];
+        matrix[0] = 1 << 31;
+        for k in 1..32 {
+            matrix[k] = matrix[k - 1] ^ (matrix[k - 1] >> 1);
+        }
+        SobolSampler {
+            samples_per_pixel,
+            matrix,
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn sobol(&self, a: u32, dimension: usize) -> u32 {
+        if dimension == 0 {
+            return a.reverse_bits();
+        }
+        let mut v = 0;
+        for (k, column) in (*self).matrix.iter().enumerate() {
+            if a >> k & 1 == 1 {
+                v ^= column;
+            }
+        }
+        v
+    }
+
+    // Picks this sample's point in a per-dimension shuffled order and moves on
+    // by `n` dimensions.
+    fn shuffled_index(&mut self, n: usize) -> (u32, u64) {
+        let h = hash(&[
+            (*self).seed,
+            (*self).pixel.0 as u64,
+            (*self).pixel.1 as u64,
+            (*self).dimension as u64,
+        ]);
+        (*self).dimension += n;
+        let spp = (*self).samples_per_pixel;
+        let index = permutation_element(((*self).index % spp) as u32, spp as u32, h as u32);
+        (index, h)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        (*self).pixel = (x, y);
+        (*self).index = index;
+        (*self).dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (index, h) = (*self).shuffled_index(1);
+        to_unit(owen_scramble((*self).sobol(index, 0), mix(h) as u32))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (index, h) = (*self).shuffled_index(2);
+        let seeds = mix(h);
+        (
+            to_unit(owen_scramble((*self).sobol(index, 0), seeds as u32)),
+            to_unit(owen_scramble((*self).sobol(index, 1), (seeds >> 32) as u32)),
+        )
+    }
+}
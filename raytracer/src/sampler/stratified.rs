@@ -0,0 +1,76 @@
+use super::{hash, hash_to_unit, permutation_element, Sampler};
+
+// rounded up, for a > 0
+fn ceil_div(a: usize, b: usize) -> usize {
+    (a - 1) / b + 1
+}
+
+// Jittered strata, with the strata of every dimension visited in an order
+// shuffled per pixel so that dimensions don't correlate.
+pub struct StratifiedSampler {
+    samples_per_pixel: usize,
+    x_strata: usize,
+    y_strata: usize,
+    seed: u64,
+    pixel: (usize, usize),
+    index: usize,
+    dimension: usize,
+}
+
+impl StratifiedSampler {
+    pub fn creat(samples_per_pixel: usize, seed: u64) -> StratifiedSampler {
+        let x_strata = ((samples_per_pixel as f64).sqrt().round() as usize).max(1);
+        StratifiedSampler {
+            samples_per_pixel,
+            x_strata,
+            y_strata: ceil_div(samples_per_pixel, x_strata),
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn dimension_hash(&mut self) -> u64 {
+        let h = hash(&[
+            (*self).seed,
+            (*self).pixel.0 as u64,
+            (*self).pixel.1 as u64,
+            (*self).dimension as u64,
+        ]);
+        (*self).dimension += 1;
+        h
+    }
+
+    fn jitter(&self, h: u64, k: u64) -> f64 {
+        hash_to_unit(hash(&[h, (*self).index as u64, k]))
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        (*self).pixel = (x, y);
+        (*self).index = index;
+        (*self).dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let n = (*self).samples_per_pixel;
+        let h = (*self).dimension_hash();
+        let stratum = permutation_element(((*self).index % n) as u32, n as u32, h as u32);
+        (stratum as f64 + (*self).jitter(h, 0)) / n as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        // with a non-square sample count some of the x * y strata stay empty
+        let n = (*self).x_strata * (*self).y_strata;
+        let h = (*self).dimension_hash();
+        (*self).dimension += 1;
+        let stratum = permutation_element(((*self).index % n) as u32, n as u32, h as u32) as usize;
+        let (sx, sy) = (stratum % (*self).x_strata, stratum / (*self).x_strata);
+        (
+            (sx as f64 + (*self).jitter(h, 1)) / (*self).x_strata as f64,
+            (sy as f64 + (*self).jitter(h, 2)) / (*self).y_strata as f64,
+        )
+    }
+}
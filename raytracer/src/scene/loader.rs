@@ -0,0 +1,884 @@
+use super::Scene;
+use crate::basic::{
+    camera::Camera,
+    vec3::{Color, Vec3},
+};
+use crate::bvh::{LinearBvh, SplitMethod};
+use crate::hittable::{
+    aarect::{XyRect, XzRect, YzRect},
+    constant_medium::ConstantMedium,
+    flip_face::FlipFace,
+    hittable_list::HittableList,
+    mesh::TriangleMesh,
+    moving_sphere::MovingSphere,
+    mybox::MyBox,
+    rotate_y::RotateY,
+    sphere::Sphere,
+    translate::Translate,
+    Hittable,
+};
+use crate::light::{
+    DeltaLight, DirectionalLight, EnvironmentLight, InfiniteLight, LightList, LightSelection,
+    PointLight, Sky, SpotLight,
+};
+use crate::material::{
+    conductor::conductor_ior, Conductor, Dielectric, DiffuseLight, Isotropic, Lambertian, Material,
+    Metal, Principled, RoughDielectric,
+};
+use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture};
+
+use serde::{de::Error as _, Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(PathBuf, std::io::Error),
+    Parse(toml::de::Error),
+    Build { line: Option<usize>, msg: String },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(path, e) => write!(f, "cannot read {}: {}", path.display(), e),
+            SceneError::Parse(e) => write!(f, "{}", e),
+            SceneError::Build {
+                line: Some(line),
+                msg,
+            } => write!(f, "{} at line {}", msg, line),
+            SceneError::Build { line: None, msg } => write!(f, "{}", msg),
+        }
+    }
+}
+
+fn default_vup() -> [f64; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_focus_dist() -> f64 {
+    10.0
+}
+
+fn default_time1() -> f64 {
+    1.0
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDef {
+    #[serde(default)]
+    background: [f64; 3],
+    #[serde(default)]
+    bvh: bool,
+    environment: Option<EnvironmentDef>,
+    sky: Option<SkyDef>,
+    image: ImageDef,
+    camera: CameraDef,
+    // Kept as raw values and deserialized one by one, so that a bad entry can be
+    // reported at its own line instead of at the end of the enclosing array.
+    #[serde(default)]
+    textures: BTreeMap<String, toml::Value>,
+    #[serde(default)]
+    materials: BTreeMap<String, toml::Value>,
+    #[serde(default)]
+    objects: Vec<toml::Value>,
+    #[serde(default)]
+    lights: Vec<toml::Value>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvironmentDef {
+    file: String,
+    #[serde(default = "default_scale")]
+    intensity: f64,
+    // degrees about the y axis
+    #[serde(default)]
+    rotation: f64,
+    // also sample it as a light, by luminance
+    #[serde(default = "default_true")]
+    importance: bool,
+}
+
+fn default_turbidity() -> f64 {
+    3.0
+}
+
+fn default_sun_radius() -> f64 {
+    0.2667
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SkyDef {
+    // towards the sun
+    sun_direction: [f64; 3],
+    #[serde(default = "default_turbidity")]
+    turbidity: f64,
+    // degrees
+    #[serde(default = "default_sun_radius")]
+    sun_radius: f64,
+    #[serde(default = "default_scale")]
+    intensity: f64,
+    #[serde(default = "default_true")]
+    importance: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ImageDef {
+    width: usize,
+    height: usize,
+    samples_per_pixel: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDef {
+    lookfrom: [f64; 3],
+    lookat: [f64; 3],
+    #[serde(default = "default_vup")]
+    vup: [f64; 3],
+    vfov: f64,
+    #[serde(default)]
+    aperture: f64,
+    #[serde(default = "default_focus_dist")]
+    focus_dist: f64,
+    #[serde(default)]
+    time0: f64,
+    #[serde(default = "default_time1")]
+    time1: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDef {
+    Solid { color: [f64; 3] },
+    Checker { odd: [f64; 3], even: [f64; 3] },
+    Noise { scale: f64 },
+    Image { file: String },
+}
+
+// A material parameter: a number, a color or the name of a texture.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ParamDef {
+    Number(f64),
+    Color([f64; 3]),
+    Texture(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDef {
+    Lambertian {
+        color: Option<[f64; 3]>,
+        texture: Option<String>,
+    },
+    Metal {
+        albedo: [f64; 3],
+        #[serde(default)]
+        fuzz: f64,
+    },
+    Dielectric {
+        ir: f64,
+    },
+    RoughDielectric {
+        ir: f64,
+        roughness: f64,
+        #[serde(default)]
+        absorption: [f64; 3],
+    },
+    Conductor {
+        // a preset, or `eta` and `k`
+        metal: Option<String>,
+        eta: Option<[f64; 3]>,
+        k: Option<[f64; 3]>,
+        roughness: f64,
+        #[serde(default)]
+        anisotropy: f64,
+    },
+    Principled {
+        base_color: ParamDef,
+        metallic: Option<ParamDef>,
+        roughness: Option<ParamDef>,
+        specular: Option<ParamDef>,
+        clearcoat: Option<ParamDef>,
+        sheen: Option<ParamDef>,
+        transmission: Option<ParamDef>,
+        emission: Option<ParamDef>,
+    },
+    DiffuseLight {
+        color: Option<[f64; 3]>,
+        texture: Option<String>,
+    },
+    Isotropic {
+        color: Option<[f64; 3]>,
+        texture: Option<String>,
+    },
+}
+
+struct ObjectDef {
+    shape: ShapeDef,
+    transforms: Vec<TransformDef>,
+    light: bool,
+}
+
+// Read by hand rather than with `serde(flatten)`, which would let unknown
+// keys through: the shared keys are taken out and the rest must be a shape.
+impl<'de> Deserialize<'de> for ObjectDef {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut table = toml::value::Table::deserialize(deserializer)?;
+        let transforms = match table.remove("transforms") {
+            Some(v) => v.try_into().map_err(D::Error::custom)?,
+            None => Default::default(),
+        };
+        let light = match table.remove("light") {
+            Some(v) => v.try_into().map_err(D::Error::custom)?,
+            None => false,
+        };
+        Ok(ObjectDef {
+            shape: toml::Value::Table(table)
+                .try_into()
+                .map_err(D::Error::custom)?,
+            transforms,
+            light,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ShapeDef {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    MovingSphere {
+        center0: [f64; 3],
+        center1: [f64; 3],
+        #[serde(default)]
+        time0: f64,
+        #[serde(default = "default_time1")]
+        time1: f64,
+        radius: f64,
+        material: String,
+    },
+    XyRect {
+        x0: f64,
+        x1: f64,
+        y0: f64,
+        y1: f64,
+        k: f64,
+        material: String,
+    },
+    XzRect {
+        x0: f64,
+        x1: f64,
+        z0: f64,
+        z1: f64,
+        k: f64,
+        material: String,
+    },
+    YzRect {
+        y0: f64,
+        y1: f64,
+        z0: f64,
+        z1: f64,
+        k: f64,
+        material: String,
+    },
+    Box {
+        p0: [f64; 3],
+        p1: [f64; 3],
+        material: String,
+    },
+    Mesh {
+        file: String,
+        material: Option<String>,
+        #[serde(default = "default_scale")]
+        scale: f64,
+    },
+    ConstantMedium {
+        boundary: Box<ObjectDef>,
+        density: f64,
+        color: Option<[f64; 3]>,
+        texture: Option<String>,
+    },
+    Group {
+        objects: Vec<ObjectDef>,
+        #[serde(default)]
+        bvh: bool,
+    },
+}
+
+impl ShapeDef {
+    // The shapes implementing the light sampling methods of `Hittable`.
+    // Triangles do as well, but scenes only reach them through a `mesh`,
+    // which keeps them behind a BVH: sampling it would need an area weighted
+    // pick among its triangles, and its pdf every triangle along the ray
+    // rather than the closest hit.
+    fn can_be_light(&self) -> bool {
+        matches!(
+            self,
+            ShapeDef::Sphere { .. }
+                | ShapeDef::XyRect { .. }
+                | ShapeDef::XzRect { .. }
+                | ShapeDef::YzRect { .. }
+        )
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TransformDef {
+    Translate { offset: [f64; 3] },
+    RotateY { angle: f64 },
+    FlipFace,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum LightDef {
+    Point {
+        position: [f64; 3],
+        intensity: [f64; 3],
+    },
+    Spot {
+        position: [f64; 3],
+        // the way the spot shines
+        direction: [f64; 3],
+        intensity: [f64; 3],
+        // half angle of the cone, degrees
+        angle: f64,
+        // degrees at the edge of the cone over which it fades out
+        #[serde(default)]
+        falloff: f64,
+    },
+    Directional {
+        // towards the light
+        direction: [f64; 3],
+        irradiance: [f64; 3],
+    },
+}
+
+fn delta_light(def: &LightDef) -> Result<Box<dyn DeltaLight>, String> {
+    Ok(match def {
+        LightDef::Point {
+            position,
+            intensity,
+        } => Box::new(PointLight {
+            position: vec3(*position),
+            intensity: vec3(*intensity),
+        }),
+        LightDef::Spot {
+            position,
+            direction,
+            intensity,
+            angle,
+            falloff,
+        } => {
+            if vec3(*direction).near_zero() {
+                return Err("direction cannot be zero".to_string());
+            }
+            if !(*angle > 0.0 && *angle <= 180.0) {
+                return Err("angle must be between 0 and 180 degrees".to_string());
+            }
+            if !(*falloff >= 0.0 && *falloff <= *angle) {
+                return Err("falloff must be between 0 and the angle".to_string());
+            }
+            Box::new(SpotLight::creat(
+                vec3(*position),
+                vec3(*direction),
+                vec3(*intensity),
+                *angle,
+                *falloff,
+            ))
+        }
+        LightDef::Directional {
+            direction,
+            irradiance,
+        } => {
+            if vec3(*direction).near_zero() {
+                return Err("direction cannot be zero".to_string());
+            }
+            Box::new(DirectionalLight::creat(vec3(*direction), vec3(*irradiance)))
+        }
+    })
+}
+
+fn vec3(a: [f64; 3]) -> Vec3 {
+    Vec3 { e: a }
+}
+
+// Finds the line of the `n`-th `[[header]]` or the `[header]` table in the source,
+// so that errors found after parsing can still point back into the file.
+fn header_line(src: &str, header: &str, n: usize) -> Option<usize> {
+    src.lines()
+        .enumerate()
+        .filter(|(_, l)| l.trim_start().starts_with(header))
+        .nth(n)
+        .map(|(i, _)| i + 1)
+}
+
+struct Builder<'a> {
+    dir: &'a Path,
+    split: SplitMethod,
+    time0: f64,
+    time1: f64,
+    textures: BTreeMap<String, Arc<dyn Texture>>,
+    materials: BTreeMap<String, Arc<dyn Material>>,
+}
+
+impl<'a> Builder<'a> {
+    fn texture(&self, def: &TextureDef) -> Result<Arc<dyn Texture>, String> {
+        Ok(match def {
+            TextureDef::Solid { color } => Arc::new(SolidColor {
+                color_value: vec3(*color),
+            }),
+            TextureDef::Checker { odd, even } => Arc::new(
+                CheckerTexture::<SolidColor, SolidColor>::creat(vec3(*even), vec3(*odd)),
+            ),
+            TextureDef::Noise { scale } => Arc::new(NoiseTexture::creat(*scale)),
+            TextureDef::Image { file } => {
+                let path = (*self).dir.join(file);
+                match ImageTexture::open(&path.to_string_lossy()) {
+                    Ok(tex) => Arc::new(tex),
+                    Err(e) => return Err(format!("cannot load image {}: {}", path.display(), e)),
+                }
+            }
+        })
+    }
+
+    fn texture_or_color(
+        &self,
+        color: &Option<[f64; 3]>,
+        texture: &Option<String>,
+    ) -> Result<Arc<dyn Texture>, String> {
+        match (color, texture) {
+            (Some(c), None) => Ok(Arc::new(SolidColor {
+                color_value: vec3(*c),
+            })),
+            (None, Some(name)) => match (*self).textures.get(name) {
+                Some(tex) => Ok(tex.clone()),
+                None => Err(format!("unknown texture `{}`", name)),
+            },
+            _ => Err("exactly one of `color` and `texture` must be given".to_string()),
+        }
+    }
+
+    fn param(&self, def: &ParamDef) -> Result<Arc<dyn Texture>, String> {
+        match def {
+            ParamDef::Number(x) => Ok(Arc::new(SolidColor {
+                color_value: vec3([*x; 3]),
+            })),
+            ParamDef::Color(c) => Ok(Arc::new(SolidColor {
+                color_value: vec3(*c),
+            })),
+            ParamDef::Texture(name) => match (*self).textures.get(name) {
+                Some(tex) => Ok(tex.clone()),
+                None => Err(format!("unknown texture `{}`", name)),
+            },
+        }
+    }
+
+    fn material(&self, def: &MaterialDef) -> Result<Arc<dyn Material>, String> {
+        Ok(match def {
+            MaterialDef::Lambertian { color, texture } => Arc::new(Lambertian {
+                albedo: (*self).texture_or_color(color, texture)?,
+            }),
+            MaterialDef::Metal { albedo, fuzz } => Arc::new(Metal::creat(vec3(*albedo), *fuzz)),
+            MaterialDef::Dielectric { ir } => Arc::new(Dielectric { ir: *ir }),
+            MaterialDef::RoughDielectric {
+                ir,
+                roughness,
+                absorption,
+            } => {
+                if *ir <= 0.0 {
+                    return Err("ir must be positive".to_string());
+                }
+                if !(0.0..=1.0).contains(roughness) {
+                    return Err("roughness must be between 0 and 1".to_string());
+                }
+                if absorption.iter().any(|a| *a < 0.0) {
+                    return Err("absorption cannot be negative".to_string());
+                }
+                Arc::new(RoughDielectric::creat(*ir, *roughness, vec3(*absorption)))
+            }
+            MaterialDef::Conductor {
+                metal,
+                eta,
+                k,
+                roughness,
+                anisotropy,
+            } => {
+                let (eta, k) = match (metal, eta, k) {
+                    (Some(name), None, None) => match conductor_ior(name) {
+                        Some(ior) => ior,
+                        None => return Err(format!("unknown metal `{}`", name)),
+                    },
+                    (None, Some(eta), Some(k)) => (vec3(*eta), vec3(*k)),
+                    _ => return Err("give either `metal` or both `eta` and `k`".to_string()),
+                };
+                if !(0.0..=1.0).contains(roughness) {
+                    return Err("roughness must be between 0 and 1".to_string());
+                }
+                if !(0.0..1.0).contains(anisotropy) {
+                    return Err("anisotropy must be at least 0 and below 1".to_string());
+                }
+                Arc::new(Conductor::creat(eta, k, *roughness, *anisotropy))
+            }
+            MaterialDef::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                clearcoat,
+                sheen,
+                transmission,
+                emission,
+            } => {
+                let mut mat = Principled::creat((*self).param(base_color)?);
+                let params = [
+                    (&mut mat.metallic, metallic),
+                    (&mut mat.roughness, roughness),
+                    (&mut mat.specular, specular),
+                    (&mut mat.clearcoat, clearcoat),
+                    (&mut mat.sheen, sheen),
+                    (&mut mat.transmission, transmission),
+                    (&mut mat.emission, emission),
+                ];
+                for (field, def) in params {
+                    if let Some(def) = def {
+                        *field = (*self).param(def)?;
+                    }
+                }
+                Arc::new(mat)
+            }
+            MaterialDef::DiffuseLight { color, texture } => Arc::new(DiffuseLight::creat_ptr(
+                (*self).texture_or_color(color, texture)?,
+            )),
+            MaterialDef::Isotropic { color, texture } => Arc::new(Isotropic {
+                albedo: (*self).texture_or_color(color, texture)?,
+            }),
+        })
+    }
+
+    fn find_material(&self, name: &str) -> Result<Arc<dyn Material>, String> {
+        match (*self).materials.get(name) {
+            Some(mat) => Ok(mat.clone()),
+            None => Err(format!("unknown material `{}`", name)),
+        }
+    }
+
+    // An object inside another one, which cannot be a light by itself.
+    fn part(&self, def: &ObjectDef) -> Result<Box<dyn Hittable>, String> {
+        if def.light {
+            return Err("only top-level objects can be lights".to_string());
+        }
+        (*self).object(def)
+    }
+
+    fn object(&self, def: &ObjectDef) -> Result<Box<dyn Hittable>, String> {
+        let mut obj: Box<dyn Hittable> = match &def.shape {
+            ShapeDef::Sphere {
+                center,
+                radius,
+                material,
+            } => Box::new(Sphere {
+                center: vec3(*center),
+                radius: *radius,
+                mat_ptr: (*self).find_material(material)?,
+            }),
+            ShapeDef::MovingSphere {
+                center0,
+                center1,
+                time0,
+                time1,
+                radius,
+                material,
+            } => Box::new(MovingSphere {
+                center0: vec3(*center0),
+                center1: vec3(*center1),
+                time0: *time0,
+                time1: *time1,
+                radius: *radius,
+                mat_ptr: (*self).find_material(material)?,
+            }),
+            ShapeDef::XyRect {
+                x0,
+                x1,
+                y0,
+                y1,
+                k,
+                material,
+            } => Box::new(XyRect {
+                x0: *x0,
+                x1: *x1,
+                y0: *y0,
+                y1: *y1,
+                k: *k,
+                mp: (*self).find_material(material)?,
+            }),
+            ShapeDef::XzRect {
+                x0,
+                x1,
+                z0,
+                z1,
+                k,
+                material,
+            } => Box::new(XzRect {
+                x0: *x0,
+                x1: *x1,
+                z0: *z0,
+                z1: *z1,
+                k: *k,
+                mp: (*self).find_material(material)?,
+            }),
+            ShapeDef::YzRect {
+                y0,
+                y1,
+                z0,
+                z1,
+                k,
+                material,
+            } => Box::new(YzRect {
+                y0: *y0,
+                y1: *y1,
+                z0: *z0,
+                z1: *z1,
+                k: *k,
+                mp: (*self).find_material(material)?,
+            }),
+            ShapeDef::Box { p0, p1, material } => Box::new(MyBox::creat(
+                vec3(*p0),
+                vec3(*p1),
+                (*self).find_material(material)?,
+            )),
+            ShapeDef::Mesh {
+                file,
+                material,
+                scale,
+            } => {
+                let default_mat = match material {
+                    Some(name) => Some((*self).find_material(name)?),
+                    None => None,
+                };
+                let path = (*self).dir.join(file);
+                match TriangleMesh::load_obj(
+                    &path.to_string_lossy(),
+                    default_mat,
+                    *scale,
+                    (*self).split,
+                ) {
+                    Ok(mesh) => Box::new(mesh),
+                    Err(e) => return Err(format!("{}", e)),
+                }
+            }
+            ShapeDef::ConstantMedium {
+                boundary,
+                density,
+                color,
+                texture,
+            } => Box::new(ConstantMedium::creat(
+                (*self).part(boundary)?,
+                *density,
+                (*self).texture_or_color(color, texture)?,
+            )),
+            ShapeDef::Group { objects, bvh } => {
+                let mut list: HittableList = Default::default();
+                for child in objects {
+                    list.add((*self).part(child)?);
+                }
+                if !*bvh {
+                    Box::new(list)
+                } else if list.objects.is_empty() {
+                    return Err("cannot build a bvh over an empty group".to_string());
+                } else {
+                    Box::new(LinearBvh::creat(
+                        list.objects,
+                        (*self).time0,
+                        (*self).time1,
+                        (*self).split,
+                    ))
+                }
+            }
+        };
+        for transform in &def.transforms {
+            obj = match transform {
+                TransformDef::Translate { offset } => Box::new(Translate {
+                    ptr: obj,
+                    offset: vec3(*offset),
+                }),
+                TransformDef::RotateY { angle } => Box::new(RotateY::creat(obj, *angle)),
+                TransformDef::FlipFace => Box::new(FlipFace { ptr: obj }),
+            };
+        }
+        Ok(obj)
+    }
+}
+
+pub fn load(
+    path: &str,
+    split: SplitMethod,
+    selection: LightSelection,
+) -> Result<Scene, SceneError> {
+    let path = Path::new(path);
+    let src = match fs::read_to_string(path) {
+        Ok(src) => src,
+        Err(e) => return Err(SceneError::Io(path.to_path_buf(), e)),
+    };
+    let def: SceneDef = toml::from_str(&src).map_err(SceneError::Parse)?;
+    if def.image.width == 0 || def.image.height == 0 || def.image.samples_per_pixel == 0 {
+        return Err(SceneError::Build {
+            line: header_line(&src, "[image]", 0),
+            msg: "image size and samples_per_pixel must be positive".to_string(),
+        });
+    }
+
+    let mut builder = Builder {
+        dir: path.parent().unwrap_or_else(|| Path::new("")),
+        split,
+        time0: def.camera.time0,
+        time1: def.camera.time1,
+        textures: Default::default(),
+        materials: Default::default(),
+    };
+    for (name, tex) in def.textures {
+        let err = |msg| SceneError::Build {
+            line: header_line(&src, &format!("[textures.{}]", name), 0),
+            msg: format!("texture `{}`: {}", name, msg),
+        };
+        let tex: TextureDef = tex.try_into().map_err(|e| err(format!("{}", e)))?;
+        let built = builder.texture(&tex).map_err(err)?;
+        builder.textures.insert(name, built);
+    }
+    for (name, mat) in def.materials {
+        let err = |msg| SceneError::Build {
+            line: header_line(&src, &format!("[materials.{}]", name), 0),
+            msg: format!("material `{}`: {}", name, msg),
+        };
+        let mat: MaterialDef = mat.try_into().map_err(|e| err(format!("{}", e)))?;
+        let built = builder.material(&mat).map_err(err)?;
+        builder.materials.insert(name, built);
+    }
+
+    let mut world: HittableList = Default::default();
+    let mut lights: HittableList = Default::default();
+    for (i, obj) in def.objects.into_iter().enumerate() {
+        let err = |msg| SceneError::Build {
+            line: header_line(&src, "[[objects]]", i),
+            msg: format!("object #{}: {}", i + 1, msg),
+        };
+        let obj: ObjectDef = obj.try_into().map_err(|e| err(format!("{}", e)))?;
+        if obj.light && !obj.shape.can_be_light() {
+            return Err(err(
+                "only spheres and rects can be sampled as lights".to_string()
+            ));
+        }
+        let built = builder.object(&obj).map_err(err)?;
+        if obj.light {
+            let shared: Arc<dyn Hittable> = Arc::from(built);
+            lights.add(Box::new(shared.clone()));
+            world.add(Box::new(shared));
+        } else {
+            world.add(built);
+        }
+    }
+    let mut delta_lights: Vec<Box<dyn DeltaLight>> = Default::default();
+    for (i, light) in def.lights.into_iter().enumerate() {
+        let err = |msg| SceneError::Build {
+            line: header_line(&src, "[[lights]]", i),
+            msg: format!("light #{}: {}", i + 1, msg),
+        };
+        let light: LightDef = light.try_into().map_err(|e| err(format!("{}", e)))?;
+        delta_lights.push(delta_light(&light).map_err(err)?);
+    }
+    if def.bvh && !world.objects.is_empty() {
+        let bvh = LinearBvh::creat(world.objects, builder.time0, builder.time1, builder.split);
+        world = Default::default();
+        world.add(Box::new(bvh));
+    }
+
+    let environment: Option<(Arc<dyn InfiniteLight>, bool)> = match (def.environment, def.sky) {
+        (Some(_), Some(_)) => {
+            return Err(SceneError::Build {
+                line: header_line(&src, "[sky]", 0),
+                msg: "a scene cannot have both an environment and a sky".to_string(),
+            })
+        }
+        (Some(env), None) => {
+            let path = builder.dir.join(&env.file);
+            let light =
+                EnvironmentLight::open(&path.to_string_lossy(), env.intensity, env.rotation)
+                    .map_err(|e| SceneError::Build {
+                        line: header_line(&src, "[environment]", 0),
+                        msg: format!("cannot load environment {}: {}", path.display(), e),
+                    })?;
+            Some((Arc::new(light), env.importance))
+        }
+        (None, Some(sky)) => {
+            let err = |msg: &str| SceneError::Build {
+                line: header_line(&src, "[sky]", 0),
+                msg: format!("sky: {}", msg),
+            };
+            if vec3(sky.sun_direction).near_zero() {
+                return Err(err("sun_direction cannot be zero"));
+            }
+            if !(1.7..=10.0).contains(&sky.turbidity) {
+                return Err(err("turbidity must be between 1.7 and 10"));
+            }
+            if !(sky.sun_radius > 0.0 && sky.sun_radius < 90.0) {
+                return Err(err("sun_radius must be between 0 and 90 degrees"));
+            }
+            let light = Sky::creat(
+                vec3(sky.sun_direction),
+                sky.turbidity,
+                sky.sun_radius,
+                sky.intensity,
+            );
+            Some((Arc::new(light), sky.importance))
+        }
+        (None, None) => None,
+    };
+    if let Some((light, true)) = &environment {
+        lights.add(Box::new(light.clone()));
+    }
+
+    let image = def.image;
+    let camera = def.camera;
+    Ok(Scene {
+        world,
+        lights: LightList::creat(lights, selection),
+        delta_lights,
+        cam: Camera::creat(
+            vec3(camera.lookfrom),
+            vec3(camera.lookat),
+            vec3(camera.vup),
+            camera.vfov,
+            image.width as f64 / image.height as f64,
+            camera.aperture,
+            camera.focus_dist,
+            camera.time0,
+            camera.time1,
+        ),
+        background: Color { e: def.background },
+        environment: environment.map(|(light, _)| light),
+        image_width: image.width,
+        image_height: image.height,
+        samples_per_pixel: image.samples_per_pixel,
+    })
+}
@@ -0,0 +1,35 @@
+pub mod loader;
+
+use crate::basic::{
+    camera::Camera,
+    vec3::{Color, Vec3},
+};
+use crate::hittable::hittable_list::HittableList;
+use crate::light::{DeltaLight, InfiniteLight, LightList};
+use std::sync::Arc;
+
+pub use loader::{load, SceneError};
+
+pub struct Scene {
+    pub world: HittableList,
+    pub lights: LightList,
+    // point, spot and directional lights, which are not part of `world`
+    pub delta_lights: Vec<Box<dyn DeltaLight>>,
+    pub cam: Camera,
+    pub background: Color,
+    // replaces `background` when set
+    pub environment: Option<Arc<dyn InfiniteLight>>,
+    pub image_width: usize,
+    pub image_height: usize,
+    pub samples_per_pixel: usize,
+}
+
+impl Scene {
+    // Radiance seen by a ray in direction `dir` that hits nothing.
+    pub fn background(&self, dir: Vec3) -> Color {
+        match &(*self).environment {
+            Some(env) => env.radiance(dir),
+            None => (*self).background,
+        }
+    }
+}
@@ -6,10 +6,17 @@ use crate::basic::{
 };
 use image::*;
 use perlin::Perlin;
+use std::sync::Arc;
 pub trait Texture: Send + Sync {
     fn value(&self, u: f64, v: f64, p: Point3) -> Color;
 }
 
+impl Texture for Arc<dyn Texture> {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+        (**self).value(u, v, p)
+    }
+}
+
 #[derive(Default, Clone, Copy)]
 pub struct SolidColor {
     pub color_value: Color,
@@ -79,14 +86,18 @@ pub struct ImageTexture {
 
 impl ImageTexture {
     pub fn creat(file: &str) -> ImageTexture {
-        let ret = image::open(file).unwrap();
+        ImageTexture::open(file).unwrap()
+    }
+
+    pub fn open(file: &str) -> ImageResult<ImageTexture> {
+        let ret = image::open(file)?;
         let w = ret.width() as usize;
         let h = ret.height() as usize;
-        ImageTexture {
+        Ok(ImageTexture {
             data: ret,
             width: w,
             height: h,
-        }
+        })
     }
 }
 